@@ -0,0 +1,494 @@
+//! Tab completion at the prompt: command names for the first word of a
+//! command, then what `complete` declares for it, or paths.
+
+pub mod spec;
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    ffi::OsString,
+    fs,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use linefeed::{
+    complete::{Completer, Completion, Suffix},
+    prompter::Prompter,
+    terminal::Terminal,
+};
+
+use crate::interpreting::{
+    builtins::BUILTINS,
+    expansion::expand,
+    interpreter::{call_function, is_a_valid_name, quote_word, Context},
+    process::capture,
+};
+
+/// Words after which a new command starts, as after `;`.
+const COMMAND_KEYWORDS: [&str; 8] = ["if", "then", "else", "elif", "do", "while", "until", "!"];
+
+thread_local! {
+    /// The shell as of the last `update`. `linefeed` wants completers to be
+    /// `Send + Sync`, which the context is not, but it calls them on the
+    /// thread reading the line.
+    static SHELL: RefCell<Context> = RefCell::new(Context::default());
+}
+
+/// The executables of the directories of `path`, found again when it
+/// changes.
+#[derive(Default)]
+struct Commands {
+    path: Option<OsString>,
+    names: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct ShellCompleter {
+    commands: Mutex<Commands>,
+}
+
+impl ShellCompleter {
+    pub fn new() -> ShellCompleter {
+        ShellCompleter::default()
+    }
+
+    /// Takes the aliases, functions, variables and completion specs
+    /// defined since the last call.
+    pub fn update(&self, ctx: &Context) {
+        SHELL.with_borrow_mut(|shell| *shell = ctx.clone());
+    }
+
+    /// The completions of `word`, which starts after `before` on the line.
+    pub fn completions(&self, before: &str, word: &str) -> Vec<Completion> {
+        self.complete(before, word, true)
+    }
+
+    /// What completing the last word of `line` would add to it when it has
+    /// a single completion, for the suggestion shown while typing; the
+    /// functions of `complete -F` are not run on every key.
+    pub fn suggestion(&self, line: &str) -> Option<String> {
+        let start = word_start(line);
+        let typed = &line[start..];
+        if typed.is_empty() {
+            return None;
+        }
+        match self.complete(&line[..start], &typed.replace('"', ""), false)[..] {
+            [ref only] => only
+                .completion
+                .strip_prefix(typed)
+                .filter(|rest| !rest.is_empty())
+                .map(|rest| rest.to_string()),
+            _ => None,
+        }
+    }
+
+    fn complete(&self, before: &str, word: &str, run_functions: bool) -> Vec<Completion> {
+        let mut ctx = SHELL.with_borrow(|shell| shell.clone());
+        let words = command_words(before);
+        let Some((name, args)) = words.split_first() else {
+            if word.contains('/') || word.starts_with('~') {
+                return complete_path(word, &ctx, &[], true);
+            }
+            return self.complete_command(word, &ctx);
+        };
+        let Some(spec) = ctx.completion_specs.get(name).cloned() else {
+            return complete_path(word, &ctx, &[], false);
+        };
+        let mut candidates = spec.candidates(args.len() + 1, word);
+        if let Some(function) = spec.function.as_ref().filter(|_| run_functions) {
+            let previous = args.last().unwrap_or(name);
+            candidates.extend(function_words(&mut ctx, function, name, word, previous));
+        }
+        candidates.retain(|candidate| candidate.starts_with(word));
+        if candidates.is_empty() {
+            return complete_path(word, &ctx, &spec.extensions, false);
+        }
+        candidates.sort();
+        candidates.dedup();
+        candidates.iter().map(|c| word_completion(c)).collect()
+    }
+
+    /// Whether `name` would run a builtin, an alias, a function or an
+    /// executable, for the highlighting of the line.
+    pub fn is_command(&self, name: &str) -> bool {
+        SHELL.with_borrow(|ctx| {
+            if name.contains('/') {
+                return is_executable(Path::new(&expand(name, ctx)));
+            }
+            BUILTINS.contains(&name)
+                || ctx.alias(name).is_some()
+                || ctx.functions.contains_key(name)
+                || self
+                    .commands(ctx)
+                    .names
+                    .binary_search_by(|n| n.as_str().cmp(name))
+                    .is_ok()
+        })
+    }
+
+    /// The executables of `$PATH`, looked for again when it has changed.
+    fn commands(&self, ctx: &Context) -> MutexGuard<'_, Commands> {
+        let mut commands = self.commands.lock().unwrap();
+        let path = ctx
+            .variables
+            .get("PATH")
+            .map(OsString::from)
+            .or_else(|| std::env::var_os("PATH"));
+        if path != commands.path {
+            commands.names = path.as_ref().map(executables).unwrap_or_default();
+            commands.path = path;
+        }
+        commands
+    }
+
+    /// Builtins, aliases, functions and the executables of `$PATH`.
+    fn complete_command(&self, word: &str, ctx: &Context) -> Vec<Completion> {
+        let commands = self.commands(ctx);
+        let mut names: Vec<&str> = BUILTINS
+            .iter()
+            .copied()
+            .chain(ctx.all_aliases().into_keys())
+            .chain(ctx.functions.keys().map(|n| n.as_str()))
+            .chain(commands.names.iter().map(|n| n.as_str()))
+            .filter(|name| name.starts_with(word))
+            .collect();
+        names.sort();
+        names.dedup();
+        names.into_iter().map(word_completion).collect()
+    }
+}
+
+impl<Term: Terminal> Completer<Term> for ShellCompleter {
+    fn complete(
+        &self,
+        word: &str,
+        prompter: &Prompter<Term>,
+        start: usize,
+        _end: usize,
+    ) -> Option<Vec<Completion>> {
+        Some(self.completions(&prompter.buffer()[..start], word))
+    }
+
+    fn word_start(&self, line: &str, end: usize, _prompter: &Prompter<Term>) -> usize {
+        word_start(&line[..end])
+    }
+
+    /// The completions are quoted already: `quote_word` knows which words
+    /// the lexer would not take whole.
+    fn unquote<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        match word.contains('"') {
+            true => Cow::Owned(word.replace('"', "")),
+            false => Cow::Borrowed(word),
+        }
+    }
+}
+
+fn word_completion(word: &str) -> Completion {
+    Completion {
+        completion: quote_word(word),
+        display: Some(word.to_string()),
+        suffix: Suffix::Default,
+    }
+}
+
+/// Runs the `-F` function of a spec in a forked shell, so that nothing it
+/// changes reaches this one, and splits what it prints into words.
+fn function_words(
+    ctx: &mut Context,
+    function: &str,
+    name: &str,
+    word: &str,
+    previous: &str,
+) -> Vec<String> {
+    let Some(body) = ctx.functions.get(function).cloned() else {
+        return Vec::new();
+    };
+    let args = [name.to_string(), word.to_string(), previous.to_string()];
+    capture(ctx, |ctx| call_function(ctx, &body, &args))
+        .split_whitespace()
+        .map(|w| w.to_string())
+        .collect()
+}
+
+/// Characters that end a word and are not part of the next one.
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || matches!(c, ';' | '|' | '&' | '(' | ')' | '{' | '}' | '<' | '>')
+}
+
+/// Where the word ending the line starts: after the last separator outside
+/// quotes, so at its opening quote when it has one.
+fn word_start(line: &str) -> usize {
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if !quoted && is_separator(c) {
+            start = i + c.len_utf8();
+        }
+    }
+    start
+}
+
+/// The words of the command a word after `before` is part of, without
+/// the assignments and keywords such as `then` in front of its name. There
+/// are none when the word is the name of a command.
+fn command_words(before: &str) -> Vec<String> {
+    let mut segment = 0;
+    let mut quoted = false;
+    for (i, c) in before.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' | '|' | '&' | '(' | ')' | '{' | '}' if !quoted => segment = i + 1,
+            _ => (),
+        }
+    }
+    let mut words = Vec::new();
+    let mut word = String::new();
+    quoted = false;
+    for c in before[segment..].chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    let name = words.iter().position(|word| {
+        !COMMAND_KEYWORDS.contains(&word.as_str())
+            && !word
+                .split_once('=')
+                .is_some_and(|(name, _)| is_a_valid_name(name))
+    });
+    words.split_off(name.unwrap_or(words.len()))
+}
+
+/// The executable files of the directories of a `$PATH`.
+fn executables(path: &OsString) -> Vec<String> {
+    let mut commands = Vec::new();
+    for dir in std::env::split_paths(path) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if is_executable(&entry.path()) {
+                commands.extend(entry.file_name().into_string().ok());
+            }
+        }
+    }
+    commands.sort();
+    commands.dedup();
+    commands
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// The entries of the directory of `word` starting with its last part,
+/// `~` and variables expanded for the lookup but kept as typed in the
+/// completion. A directory is left inside its opening quote, so that the
+/// completion can go on in it. Files must have one of `extensions` when
+/// there are some.
+fn complete_path(
+    word: &str,
+    ctx: &Context,
+    extensions: &[String],
+    executable: bool,
+) -> Vec<Completion> {
+    if word == "~" {
+        return vec![Completion {
+            completion: "~/".to_string(),
+            display: None,
+            suffix: Suffix::None,
+        }];
+    }
+    let (dir, prefix) = match word.rfind('/') {
+        Some(slash) => word.split_at(slash + 1),
+        None => ("", word),
+    };
+    let lookup = match expand(dir, ctx) {
+        lookup if lookup.is_empty() => ".".to_string(),
+        lookup => lookup,
+    };
+    let Ok(entries) = fs::read_dir(lookup) else {
+        return Vec::new();
+    };
+    let mut completions = Vec::new();
+    for entry in entries.flatten() {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+            continue;
+        }
+        let is_dir = entry.path().is_dir();
+        if executable && !is_dir && !is_executable(&entry.path()) {
+            continue;
+        }
+        let extension = Path::new(&name).extension().and_then(|e| e.to_str());
+        if !extensions.is_empty()
+            && !is_dir
+            && !extension.is_some_and(|e| extensions.iter().any(|x| x == e))
+        {
+            continue;
+        }
+        let (completion, display, suffix) = match is_dir {
+            true => {
+                let path = quote_word(&format!("{dir}{name}/"));
+                (
+                    path.trim_end_matches('"').to_string(),
+                    name + "/",
+                    Suffix::None,
+                )
+            }
+            false => (quote_word(&format!("{dir}{name}")), name, Suffix::Default),
+        };
+        completions.push(Completion {
+            completion,
+            display: Some(display),
+            suffix,
+        });
+    }
+    // the prompt inserts the common prefix of several completions, which
+    // must not lose the word typed so far to a quote only some of them have
+    if completions.iter().any(|c| c.completion.starts_with('"')) {
+        for c in completions.iter_mut() {
+            if !c.completion.starts_with('"') {
+                c.completion.insert(0, '"');
+            }
+        }
+    }
+    completions.sort_by(|a, b| a.display.cmp(&b.display));
+    completions
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreting::{interpreter::Context, script::run_source};
+
+    use super::{command_words, complete_path, word_start, ShellCompleter};
+
+    #[test]
+    fn test_word_start() {
+        assert_eq!(word_start("ls"), 0);
+        assert_eq!(word_start("ls -l sr"), 6);
+        assert_eq!(word_start("cat <in|gr"), 8);
+        assert_eq!(word_start("cd \"My Doc"), 3);
+        assert_eq!(word_start("echo \"a b\" c"), 11);
+    }
+
+    #[test]
+    fn test_command_words() {
+        let empty: Vec<String> = Vec::new();
+        assert_eq!(command_words(""), empty);
+        assert_eq!(command_words("  "), empty);
+        assert_eq!(command_words("ls | "), empty);
+        assert_eq!(command_words("true && "), empty);
+        assert_eq!(command_words("A=1 B=2 "), empty);
+        assert_eq!(command_words("if true; then "), empty);
+        assert_eq!(command_words("(cd /; "), empty);
+        assert_eq!(command_words("ls "), vec!["ls"]);
+        assert_eq!(command_words("A=1 git add "), vec!["git", "add"]);
+        assert_eq!(command_words("echo \"a; b\" "), vec!["echo", "a; b"]);
+    }
+
+    #[test]
+    fn test_paths() {
+        let dir = std::env::temp_dir().join(format!("tinysh-complete-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("My Docs")).unwrap();
+        std::fs::create_dir_all(dir.join("music")).unwrap();
+        std::fs::write(dir.join("main.rs"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+        let mut ctx = Context::new();
+        ctx.variables
+            .insert("D".to_string(), dir.display().to_string());
+
+        let completions = complete_path("$D/m", &ctx, &[], false);
+        let texts: Vec<&str> = completions.iter().map(|c| c.completion.as_str()).collect();
+        assert_eq!(texts, vec!["$D/main.rs", "$D/music/"]);
+        assert_eq!(completions[1].display.as_deref(), Some("music/"));
+
+        let completions = complete_path("$D/My", &ctx, &[], false);
+        assert_eq!(completions[0].completion, "\"$D/My Docs/");
+        assert_eq!(complete_path("$D/.h", &ctx, &[], false).len(), 1);
+        let completions = complete_path("$D/", &ctx, &[], false);
+        assert_eq!(completions.len(), 3);
+        assert_eq!(completions[0].completion, "\"$D/My Docs/");
+        assert_eq!(completions[1].completion, "\"$D/main.rs");
+        // a command typed with a path is an executable or a directory
+        assert_eq!(complete_path("$D/", &ctx, &[], true).len(), 2);
+        assert_eq!(
+            complete_path("$D/m", &ctx, &["toml".to_string()], false).len(),
+            1
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_commands() {
+        let completer = ShellCompleter::new();
+        let mut ctx = Context::new();
+        ctx.config
+            .aliases
+            .insert("ll".to_string(), "ls -l".to_string());
+        ctx.variables
+            .insert("PATH".to_string(), "/nonexistent".to_string());
+        completer.update(&ctx);
+        let names = |before: &str, word: &str| -> Vec<String> {
+            completer
+                .completions(before, word)
+                .into_iter()
+                .map(|c| c.completion)
+                .collect()
+        };
+        assert_eq!(names("", "l"), vec!["ll", "local"]);
+        assert_eq!(names("true; ", "exp"), vec!["export"]);
+        assert_eq!(names("echo ", "exp"), Vec::<String>::new());
+        assert_eq!(completer.suggestion("expo"), Some("rt".to_string()));
+        assert_eq!(completer.suggestion("l"), None);
+        assert_eq!(completer.suggestion("ls "), None);
+        assert!(completer.is_command("ll") && completer.is_command("export"));
+        assert!(!completer.is_command("ls") && !completer.is_command("./nonexistent"));
+    }
+
+    #[test]
+    fn test_specs() {
+        let completer = ShellCompleter::new();
+        let mut ctx = Context::new();
+        run_source(
+            "complete -W \"start stop\" -O --force svc\n\
+             _hosts() { seen=1; echo $2-one \"$3\"; }\n\
+             complete -F _hosts ssh",
+            &mut ctx,
+        );
+        completer.update(&ctx);
+        let names = |before: &str, word: &str| -> Vec<String> {
+            completer
+                .completions(before, word)
+                .into_iter()
+                .map(|c| c.completion)
+                .collect()
+        };
+        assert_eq!(names("svc ", ""), vec!["start", "stop"]);
+        assert_eq!(names("svc start ", "st"), vec!["start", "stop"]);
+        assert_eq!(names("svc ", "--"), vec!["--force"]);
+        assert_eq!(completer.suggestion("svc sta"), Some("rt".to_string()));
+        // the functions fork, so they are checked in `tests/completion.rs`
+        assert_eq!(completer.suggestion("ssh x"), None);
+    }
+}
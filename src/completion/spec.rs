@@ -0,0 +1,237 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{git::Repository, interpreting::interpreter::quote_word};
+
+/// Generators `-A` takes, whose words depend on the current directory.
+const ACTIONS: [&str; 2] = ["git-branches", "make-targets"];
+
+/// The specs every interactive shell starts with; the rc file can replace
+/// them with `complete` or drop them with `complete -r`.
+const DEFAULT_SPECS: [&[&str]; 3] = [
+    &[
+        "-S",
+        "add bisect blame branch checkout cherry-pick clean clone commit config diff fetch grep \
+         init log merge mv pull push rebase remote reset restore revert rm show stash status \
+         switch tag worktree",
+        "-O",
+        "--help --version --no-pager",
+        "-A",
+        "git-branches",
+        "git",
+    ],
+    &[
+        "-S",
+        "add bench build check clean clippy doc fetch fix fmt init install metadata new package \
+         publish remove run search test tree uninstall update",
+        "-O",
+        "--all-features --all-targets --bin --example --features --help --lib --manifest-path \
+         --no-default-features --package --quiet --release --target --test --verbose \
+         --workspace",
+        "cargo",
+    ],
+    &[
+        "-O",
+        "--always-make --directory --dry-run --file --jobs --keep-going --silent",
+        "-A",
+        "make-targets",
+        "make",
+    ],
+];
+
+/// What `complete` declares for the arguments of a command. Files are
+/// offered when none of its words match.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompletionSpec {
+    /// `-W`: words offered for every argument
+    pub words: Vec<String>,
+    /// `-S`: words offered for the first argument only
+    pub subcommands: Vec<String>,
+    /// `-O`: words offered for an argument starting with `-`
+    pub flags: Vec<String>,
+    /// `-A`: generated words, see `ACTIONS`
+    pub actions: Vec<String>,
+    /// `-F`: a function printing the words, called with the command, the
+    /// word being completed and the one before it
+    pub function: Option<String>,
+    /// `-X`: extensions of the files offered, without their dot
+    pub extensions: Vec<String>,
+}
+
+impl CompletionSpec {
+    /// Reads the options of `complete`, and returns the spec with the
+    /// command names following them.
+    pub fn parse(args: &[String]) -> Result<(CompletionSpec, &[String]), String> {
+        let mut spec = CompletionSpec::default();
+        let mut i = 0;
+        while let Some(option) = args.get(i).filter(|a| a.starts_with('-')) {
+            let value = args
+                .get(i + 1)
+                .ok_or_else(|| format!("{option}: option requires an argument"))?;
+            let list = value.split_whitespace().map(|w| w.to_string());
+            match option.as_str() {
+                "-W" => spec.words.extend(list),
+                "-S" => spec.subcommands.extend(list),
+                "-O" => spec.flags.extend(list),
+                "-A" => {
+                    for action in list {
+                        if !ACTIONS.contains(&action.as_str()) {
+                            return Err(format!("{action}: unknown action"));
+                        }
+                        spec.actions.push(action);
+                    }
+                }
+                "-F" => spec.function = Some(value.clone()),
+                "-X" => spec
+                    .extensions
+                    .extend(list.map(|e| e.trim_start_matches('.').to_string())),
+                _ => return Err(format!("{option}: invalid option")),
+            }
+            i += 2;
+        }
+        Ok((spec, &args[i..]))
+    }
+
+    /// The `complete` command that declares the spec for `name`.
+    pub fn to_command(&self, name: &str) -> String {
+        let mut command = "complete".to_string();
+        for (option, list) in [
+            ("-W", &self.words),
+            ("-S", &self.subcommands),
+            ("-O", &self.flags),
+            ("-A", &self.actions),
+            ("-X", &self.extensions),
+        ] {
+            if !list.is_empty() {
+                command += &format!(" {option} {}", quote_word(&list.join(" ")));
+            }
+        }
+        if let Some(function) = &self.function {
+            command += &format!(" -F {}", quote_word(function));
+        }
+        command + " " + &quote_word(name)
+    }
+
+    /// The words of the spec for the argument at `position`, from 1, the
+    /// function left to the caller which can run it.
+    pub fn candidates(&self, position: usize, word: &str) -> Vec<String> {
+        let mut candidates = self.words.clone();
+        if position == 1 {
+            candidates.extend(self.subcommands.iter().cloned());
+        }
+        if word.starts_with('-') {
+            candidates.extend(self.flags.iter().cloned());
+        }
+        let cwd = std::env::current_dir().unwrap_or_default();
+        for action in &self.actions {
+            match action.as_str() {
+                "git-branches" => candidates.extend(
+                    Repository::discover(&cwd)
+                        .map(|r| r.branches())
+                        .unwrap_or_default(),
+                ),
+                "make-targets" => candidates.extend(make_targets(&cwd)),
+                _ => (),
+            }
+        }
+        candidates
+    }
+}
+
+/// The specs for `git`, `cargo` and `make`.
+pub fn default_specs() -> HashMap<String, CompletionSpec> {
+    let mut specs = HashMap::new();
+    for args in DEFAULT_SPECS {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        if let Ok((spec, names)) = CompletionSpec::parse(&args) {
+            for name in names {
+                specs.insert(name.clone(), spec.clone());
+            }
+        }
+    }
+    specs
+}
+
+/// The targets of the makefile `make` would read in `dir`. Special
+/// targets such as `.PHONY`, pattern rules and variables are left out.
+pub fn make_targets(dir: &Path) -> Vec<String> {
+    let Some(contents) = ["GNUmakefile", "makefile", "Makefile"]
+        .iter()
+        .find_map(|name| fs::read_to_string(dir.join(name)).ok())
+    else {
+        return Vec::new();
+    };
+    let mut targets = Vec::new();
+    for line in contents.lines() {
+        if line.starts_with(['\t', ' ', '#']) {
+            continue;
+        }
+        let Some((names, rest)) = line.split_once(':') else {
+            continue;
+        };
+        // `:=` and `::=` assign, `a = b:c` too
+        if rest.trim_start_matches(':').starts_with('=') || names.contains(['=', '$', '%']) {
+            continue;
+        }
+        targets.extend(
+            names
+                .split_whitespace()
+                .filter(|name| !name.starts_with('.'))
+                .map(|name| name.to_string()),
+        );
+    }
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+#[cfg(test)]
+mod test {
+    use super::{default_specs, make_targets, CompletionSpec};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let args = args(&["-W", "a b", "-X", ".rs toml", "-F", "_f", "x", "y"]);
+        let (spec, names) = CompletionSpec::parse(&args).unwrap();
+        assert_eq!(spec.words, vec!["a", "b"]);
+        assert_eq!(spec.extensions, vec!["rs", "toml"]);
+        assert_eq!(spec.function, Some("_f".to_string()));
+        assert_eq!(names, ["x", "y"]);
+        assert_eq!(
+            spec.to_command("x"),
+            "complete -W \"a b\" -X \"rs toml\" -F _f x"
+        );
+        assert!(CompletionSpec::parse(&["-W".to_string()]).is_err());
+        assert!(CompletionSpec::parse(&["-Z".to_string(), "a".to_string()]).is_err());
+        assert!(CompletionSpec::parse(&["-A".to_string(), "nope".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_candidates() {
+        let specs = default_specs();
+        let cargo = &specs["cargo"];
+        assert!(cargo.candidates(1, "b").contains(&"build".to_string()));
+        assert!(!cargo.candidates(2, "b").contains(&"build".to_string()));
+        assert!(cargo
+            .candidates(2, "--r")
+            .contains(&"--release".to_string()));
+        assert!(specs.contains_key("git") && specs.contains_key("make"));
+    }
+
+    #[test]
+    fn test_make_targets() {
+        let dir = std::env::temp_dir().join(format!("tinysh-make-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("Makefile"),
+            "CC := gcc\nFLAGS = -a:b\n.PHONY: all clean\nall build: main.o\n\t$(CC) -o x\n\
+             %.o: %.c\nclean:\n\trm -f *.o\n# doc: nothing\n",
+        )
+        .unwrap();
+        assert_eq!(make_targets(&dir), vec!["all", "build", "clean"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use ansi_term::Color;
+use serde::{Deserialize, Serialize};
+
+/// Settings read from `~/.config/tinysh/config.toml` by `confy`, which
+/// writes the defaults there on first start. Missing keys take their
+/// default value so that older files keep loading.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub prompt: String,
+    pub continuation_prompt: String,
+    /// A colour name such as `cyan`, or a 256-colour number
+    pub prompt_colour: String,
+    pub banner_colour: String,
+    /// Shown at the right of the line when `$RPS1` is not set
+    pub right_prompt: String,
+    /// Whether entered commands keep only `transient_prompt_text` as their
+    /// prompt in the scrollback
+    pub transient_prompt: bool,
+    pub transient_prompt_text: String,
+    /// Whether the git segment comes before `prompt`, see `\g` for `PS1`
+    pub git_prompt: bool,
+    /// Milliseconds the git segment may take before it is cut short
+    pub git_budget_ms: u64,
+    /// Number of lines the history keeps
+    pub history_size: usize,
+    /// Whether the prompt starts with `verbose` on
+    pub verbose: bool,
+    /// Directories appended to `$PATH` on start and on `config reload`
+    pub path: Vec<String>,
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            prompt: "tinysh> ".to_string(),
+            continuation_prompt: "> ".to_string(),
+            prompt_colour: "cyan".to_string(),
+            banner_colour: "blue".to_string(),
+            right_prompt: String::new(),
+            transient_prompt: false,
+            transient_prompt_text: "\\$ ".to_string(),
+            git_prompt: false,
+            git_budget_ms: 50,
+            history_size: 1000,
+            verbose: true,
+            path: Vec::new(),
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+const APP_NAME: &str = "tinysh";
+const CONFIG_NAME: &str = "config";
+
+impl Config {
+    /// Loads a configuration file, creating it with the defaults when it
+    /// does not exist yet.
+    pub fn load(file: &Path) -> Result<Config, String> {
+        confy::load_path(file).map_err(|e| e.to_string())
+    }
+
+    pub fn store(&self, file: &Path) -> Result<(), String> {
+        confy::store_path(file, self).map_err(|e| e.to_string())
+    }
+
+    /// Where the prompt reads its configuration.
+    pub fn file() -> Option<PathBuf> {
+        confy::get_configuration_file_path(APP_NAME, CONFIG_NAME).ok()
+    }
+
+    /// Every key with its value, aliases as `aliases.NAME`.
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let mut entries: Vec<(String, String)> = [
+            "prompt",
+            "continuation_prompt",
+            "prompt_colour",
+            "banner_colour",
+            "right_prompt",
+            "transient_prompt",
+            "transient_prompt_text",
+            "git_prompt",
+            "git_budget_ms",
+            "history_size",
+            "verbose",
+            "path",
+        ]
+        .iter()
+        .filter_map(|key| Some((key.to_string(), self.get(key)?)))
+        .collect();
+        entries.extend(
+            self.aliases
+                .iter()
+                .map(|(name, value)| (format!("aliases.{name}"), value.clone())),
+        );
+        entries
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        Some(match key {
+            "prompt" => self.prompt.clone(),
+            "continuation_prompt" => self.continuation_prompt.clone(),
+            "prompt_colour" => self.prompt_colour.clone(),
+            "banner_colour" => self.banner_colour.clone(),
+            "right_prompt" => self.right_prompt.clone(),
+            "transient_prompt" => self.transient_prompt.to_string(),
+            "transient_prompt_text" => self.transient_prompt_text.clone(),
+            "git_prompt" => self.git_prompt.to_string(),
+            "git_budget_ms" => self.git_budget_ms.to_string(),
+            "history_size" => self.history_size.to_string(),
+            "verbose" => self.verbose.to_string(),
+            "path" => self.path.join(":"),
+            key => self.aliases.get(key.strip_prefix("aliases.")?)?.clone(),
+        })
+    }
+
+    /// Changes a key from its textual value; `path` takes a `:`-separated
+    /// list and an empty value removes an alias.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "prompt" => self.prompt = value.to_string(),
+            "continuation_prompt" => self.continuation_prompt = value.to_string(),
+            "prompt_colour" | "banner_colour" => {
+                if parse_colour(value).is_none() {
+                    return Err(format!("{value}: unknown colour"));
+                }
+                if key == "prompt_colour" {
+                    self.prompt_colour = value.to_string();
+                } else {
+                    self.banner_colour = value.to_string();
+                }
+            }
+            "history_size" => {
+                self.history_size = value
+                    .parse()
+                    .map_err(|_| format!("{value}: number expected"))?
+            }
+            "verbose" => {
+                self.verbose = value
+                    .parse()
+                    .map_err(|_| format!("{value}: `true` or `false` expected"))?
+            }
+            "right_prompt" => self.right_prompt = value.to_string(),
+            "transient_prompt_text" => self.transient_prompt_text = value.to_string(),
+            "transient_prompt" => {
+                self.transient_prompt = value
+                    .parse()
+                    .map_err(|_| format!("{value}: `true` or `false` expected"))?
+            }
+            "git_prompt" => {
+                self.git_prompt = value
+                    .parse()
+                    .map_err(|_| format!("{value}: `true` or `false` expected"))?
+            }
+            "git_budget_ms" => {
+                self.git_budget_ms = value
+                    .parse()
+                    .map_err(|_| format!("{value}: number expected"))?
+            }
+            "path" => {
+                self.path = value
+                    .split(':')
+                    .filter(|dir| !dir.is_empty())
+                    .map(|dir| dir.to_string())
+                    .collect()
+            }
+            key => match key.strip_prefix("aliases.") {
+                Some(name) if !name.is_empty() => {
+                    if value.is_empty() {
+                        self.aliases.remove(name);
+                    } else {
+                        self.aliases.insert(name.to_string(), value.to_string());
+                    }
+                }
+                _ => return Err(format!("{key}: unknown key")),
+            },
+        }
+        Ok(())
+    }
+
+    /// Appends the `path` directories that `$PATH` does not have yet.
+    pub fn apply_path(&self) {
+        let current = std::env::var_os("PATH").unwrap_or_default();
+        let mut dirs: Vec<PathBuf> = std::env::split_paths(&current).collect();
+        for dir in &self.path {
+            let dir = PathBuf::from(dir);
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+        if let Ok(path) = std::env::join_paths(dirs) {
+            if path != current {
+                std::env::set_var("PATH", path);
+            }
+        }
+    }
+}
+
+/// The eight named terminal colours, or a number of the 256-colour palette.
+pub fn parse_colour(name: &str) -> Option<Color> {
+    Some(match name.to_lowercase().as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "purple" | "magenta" => Color::Purple,
+        "cyan" => Color::Cyan,
+        "white" => Color::White,
+        n => Color::Fixed(n.parse().ok()?),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use ansi_term::Color;
+
+    use super::{parse_colour, Config};
+
+    #[test]
+    fn test_get_and_set() {
+        let mut config = Config::default();
+        assert_eq!(config.get("prompt"), Some("tinysh> ".to_string()));
+        assert_eq!(config.set("history_size", "20"), Ok(()));
+        assert_eq!(config.history_size, 20);
+        assert!(config.set("history_size", "lots").is_err());
+        assert!(config.set("verbose", "maybe").is_err());
+        assert!(config.set("prompt_colour", "mauve").is_err());
+        assert!(config.set("nope", "1").is_err());
+        assert_eq!(config.set("path", "/a::/b"), Ok(()));
+        assert_eq!(config.path, vec!["/a".to_string(), "/b".to_string()]);
+        assert_eq!(config.set("aliases.ll", "ls -l"), Ok(()));
+        assert_eq!(config.get("aliases.ll"), Some("ls -l".to_string()));
+        assert_eq!(
+            config.entries().last(),
+            Some(&("aliases.ll".to_string(), "ls -l".to_string()))
+        );
+        assert_eq!(config.set("aliases.ll", ""), Ok(()));
+        assert_eq!(config.get("aliases.ll"), None);
+    }
+
+    #[test]
+    fn test_colours() {
+        assert_eq!(parse_colour("Cyan"), Some(Color::Cyan));
+        assert_eq!(parse_colour("208"), Some(Color::Fixed(208)));
+        assert_eq!(parse_colour("256"), None);
+    }
+}
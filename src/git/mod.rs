@@ -0,0 +1,658 @@
+//! The state of the git repository around the working directory, read from
+//! `.git` directly so that the prompt does not have to start `git`.
+
+pub mod objects;
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use objects::{to_hex, Store};
+
+/// A deadline after which the remaining work is given up.
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    deadline: Instant,
+}
+
+impl Budget {
+    pub fn new(duration: Duration) -> Budget {
+        Budget {
+            deadline: Instant::now() + duration,
+        }
+    }
+
+    fn exceeded(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Head {
+    Branch(String),
+    /// The abbreviated id of the commit checked out
+    Detached(String),
+}
+
+/// What the prompt shows; `None` when it could not be found in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    pub head: Head,
+    /// Commits on the branch and not on its upstream, and the reverse
+    pub ahead_behind: Option<(usize, usize)>,
+    pub staged: Option<bool>,
+    pub dirty: Option<bool>,
+}
+
+impl Status {
+    /// `(main +* ↑1↓2)`: `+` for staged changes, `*` for changes in the
+    /// work tree, `?` when the time ran out before knowing, then the
+    /// commits ahead of and behind the upstream.
+    pub fn segment(&self) -> String {
+        let mut result = match &self.head {
+            Head::Branch(name) => name.clone(),
+            Head::Detached(id) => format!("@{id}"),
+        };
+        let markers: String = [(self.staged, '+'), (self.dirty, '*')]
+            .iter()
+            .filter_map(|(state, marker)| match state {
+                Some(true) => Some(*marker),
+                Some(false) => None,
+                None => Some('?'),
+            })
+            .collect();
+        if !markers.is_empty() {
+            result.push(' ');
+            result += &markers;
+        }
+        match self.ahead_behind {
+            Some((0, 0)) | None => (),
+            Some((ahead, behind)) => {
+                result.push(' ');
+                if ahead > 0 {
+                    result += &format!("↑{ahead}");
+                }
+                if behind > 0 {
+                    result += &format!("↓{behind}");
+                }
+            }
+        }
+        format!("({result})")
+    }
+}
+
+/// One file of the index, with what is needed to tell it changed.
+struct IndexEntry {
+    path: String,
+    id: String,
+    mode: u32,
+    mtime: (u32, u32),
+    size: u32,
+    /// Conflicted files appear once per side, with a stage above 0.
+    stage: u16,
+    /// `assume-unchanged` or `skip-worktree`, not checked against the disk
+    ignored: bool,
+}
+
+pub struct Repository {
+    /// `.git`, or the directory of a linked worktree
+    git_dir: PathBuf,
+    /// Where refs and objects are shared between the worktrees
+    common_dir: PathBuf,
+    work_tree: PathBuf,
+}
+
+impl Repository {
+    /// The repository containing `start`, looking up through its parents.
+    pub fn discover(start: &Path) -> Option<Repository> {
+        for dir in start.ancestors() {
+            let dot_git = dir.join(".git");
+            let git_dir = if dot_git.is_dir() {
+                dot_git
+            } else if dot_git.is_file() {
+                // `gitdir: path` in worktrees and submodules
+                let content = fs::read_to_string(&dot_git).ok()?;
+                dir.join(content.strip_prefix("gitdir:")?.trim())
+            } else {
+                continue;
+            };
+            let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+                Ok(common) => git_dir.join(common.trim()),
+                Err(_) => git_dir.clone(),
+            };
+            return Some(Repository {
+                git_dir,
+                common_dir,
+                work_tree: dir.to_path_buf(),
+            });
+        }
+        None
+    }
+
+    /// The id a ref points to, following symbolic refs and looking into
+    /// `packed-refs` for those without a file.
+    fn resolve(&self, name: &str) -> Option<String> {
+        let mut name = name.to_string();
+        for _ in 0..5 {
+            let content = [&self.git_dir, &self.common_dir]
+                .iter()
+                .find_map(|dir| fs::read_to_string(dir.join(&name)).ok());
+            match content {
+                Some(content) => match content.trim().strip_prefix("ref:") {
+                    Some(target) => name = target.trim().to_string(),
+                    None => return Some(content.trim().to_string()),
+                },
+                None => {
+                    let packed = fs::read_to_string(self.common_dir.join("packed-refs")).ok()?;
+                    return packed.lines().find_map(|line| {
+                        let (id, reference) = line.split_once(' ')?;
+                        (reference == name && !id.starts_with(['#', '^'])).then(|| id.to_string())
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Local and remote-tracking branch names, loose or packed, for
+    /// completion.
+    pub fn branches(&self) -> Vec<String> {
+        fn walk(dir: &Path, prefix: &str, names: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+                let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+                if entry.path().is_dir() {
+                    walk(&entry.path(), &format!("{name}/"), names);
+                } else {
+                    names.push(name);
+                }
+            }
+        }
+        let mut names = Vec::new();
+        walk(&self.common_dir.join("refs/heads"), "", &mut names);
+        walk(&self.common_dir.join("refs/remotes"), "", &mut names);
+        if let Ok(packed) = fs::read_to_string(self.common_dir.join("packed-refs")) {
+            names.extend(packed.lines().filter_map(|line| {
+                let (_, reference) = line.split_once(' ')?;
+                let name = reference
+                    .strip_prefix("refs/heads/")
+                    .or_else(|| reference.strip_prefix("refs/remotes/"))?;
+                Some(name.to_string())
+            }));
+        }
+        names.retain(|name| !name.ends_with("/HEAD"));
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The branch checked out, or the commit for a detached `HEAD`.
+    fn head(&self) -> Option<Head> {
+        let content = fs::read_to_string(self.git_dir.join("HEAD")).ok()?;
+        let content = content.trim();
+        Some(match content.strip_prefix("ref:") {
+            Some(reference) => {
+                let reference = reference.trim();
+                Head::Branch(
+                    reference
+                        .strip_prefix("refs/heads/")
+                        .unwrap_or(reference)
+                        .to_string(),
+                )
+            }
+            None => Head::Detached(content.get(..7)?.to_string()),
+        })
+    }
+
+    /// The ref a branch tracks, from `branch.NAME.remote` and
+    /// `branch.NAME.merge` in the configuration.
+    fn upstream(&self, branch: &str) -> Option<String> {
+        let config = fs::read_to_string(self.common_dir.join("config")).ok()?;
+        let section = format!("[branch \"{branch}\"]");
+        let mut in_section = false;
+        let mut remote = None;
+        let mut merge = None;
+        for line in config.lines() {
+            let line = line.trim();
+            if line.starts_with('[') {
+                in_section = line == section;
+                continue;
+            }
+            if !in_section {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim().to_string();
+                match key.trim().to_lowercase().as_str() {
+                    "remote" => remote = Some(value),
+                    "merge" => merge = Some(value),
+                    _ => (),
+                }
+            }
+        }
+        let merge = merge?;
+        match remote?.as_str() {
+            "." => Some(merge),
+            remote => Some(format!(
+                "refs/remotes/{remote}/{}",
+                merge.strip_prefix("refs/heads/")?
+            )),
+        }
+    }
+
+    fn index(&self) -> Option<Vec<IndexEntry>> {
+        parse_index(&fs::read(self.git_dir.join("index")).ok()?)
+    }
+
+    /// Whether a file of the index changed on disk, by its size and
+    /// modification time as git records them.
+    fn dirty(&self, entries: &[IndexEntry], budget: &Budget) -> Option<bool> {
+        for entry in entries {
+            if budget.exceeded() {
+                return None;
+            }
+            if entry.stage != 0 {
+                return Some(true);
+            }
+            // submodules are directories whose time says nothing
+            if entry.ignored || entry.mode == 0o160000 {
+                continue;
+            }
+            match fs::symlink_metadata(self.work_tree.join(&entry.path)) {
+                Ok(metadata) => {
+                    let (mtime, size) = stat(&metadata);
+                    let same_time = mtime.0 == entry.mtime.0
+                        && (entry.mtime.1 == 0 || mtime.1 == entry.mtime.1);
+                    if !same_time || size != entry.size {
+                        return Some(true);
+                    }
+                }
+                Err(_) => return Some(true),
+            }
+        }
+        Some(false)
+    }
+
+    /// Whether the index differs from the tree of `HEAD`.
+    fn staged(
+        &self,
+        store: &Store,
+        entries: &[IndexEntry],
+        head: Option<&str>,
+        budget: &Budget,
+    ) -> Option<bool> {
+        let mut files = HashMap::new();
+        if let Some(head) = head {
+            flatten_tree(store, &store.commit_tree(head)?, "", &mut files, budget)?;
+        }
+        if files.len() != entries.len() {
+            return Some(true);
+        }
+        Some(entries.iter().any(|entry| {
+            files.get(&entry.path) != Some(&(entry.mode, entry.id.clone())) || entry.stage != 0
+        }))
+    }
+
+    pub fn status(&self, budget: &Budget) -> Option<Status> {
+        let head = self.head()?;
+        let head_id = self.resolve("HEAD");
+        let store = Store::open(self.common_dir.join("objects"));
+        let entries = self.index().unwrap_or_default();
+        let dirty = self.dirty(&entries, budget);
+        let staged = self.staged(&store, &entries, head_id.as_deref(), budget);
+        let ahead_behind = match (&head, &head_id) {
+            (Head::Branch(name), Some(id)) => self
+                .upstream(name)
+                .and_then(|upstream| self.resolve(&upstream))
+                .and_then(|upstream| ahead_behind(&store, id, &upstream, budget)),
+            _ => None,
+        };
+        Some(Status {
+            head,
+            ahead_behind,
+            staged,
+            dirty,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn stat(metadata: &fs::Metadata) -> ((u32, u32), u32) {
+    use std::os::unix::fs::MetadataExt;
+    (
+        (metadata.mtime() as u32, metadata.mtime_nsec() as u32),
+        metadata.size() as u32,
+    )
+}
+
+#[cfg(not(unix))]
+fn stat(metadata: &fs::Metadata) -> ((u32, u32), u32) {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    (
+        (mtime.as_secs() as u32, mtime.subsec_nanos()),
+        metadata.len() as u32,
+    )
+}
+
+fn flatten_tree(
+    store: &Store,
+    tree: &str,
+    prefix: &str,
+    files: &mut HashMap<String, (u32, String)>,
+    budget: &Budget,
+) -> Option<()> {
+    if budget.exceeded() {
+        return None;
+    }
+    for (mode, name, id) in store.tree(tree)? {
+        let path = format!("{prefix}{name}");
+        if mode == 0o40000 {
+            flatten_tree(store, &id, &format!("{path}/"), files, budget)?;
+        } else {
+            files.insert(path, (mode, id));
+        }
+    }
+    Some(())
+}
+
+/// Reads the entries of an index file, in version 2, 3 or 4.
+fn parse_index(data: &[u8]) -> Option<Vec<IndexEntry>> {
+    let u32_at = |position: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(
+            data.get(position..position + 4)?.try_into().ok()?,
+        ))
+    };
+    if data.get(..4)? != b"DIRC" {
+        return None;
+    }
+    let version = u32_at(4)?;
+    if !(2..=4).contains(&version) {
+        return None;
+    }
+    let count = u32_at(8)? as usize;
+    let mut entries = Vec::with_capacity(count);
+    let mut position = 12;
+    let mut previous = String::new();
+    for _ in 0..count {
+        let start = position;
+        let mtime = (u32_at(start + 8)?, u32_at(start + 12)?);
+        let mode = u32_at(start + 24)?;
+        let size = u32_at(start + 36)?;
+        let id = to_hex(data.get(start + 40..start + 60)?);
+        let flags = u16::from_be_bytes(data.get(start + 60..start + 62)?.try_into().ok()?);
+        position = start + 62;
+        let mut ignored = flags & 0x8000 != 0;
+        if flags & 0x4000 != 0 && version >= 3 {
+            let extended = u16::from_be_bytes(data.get(position..position + 2)?.try_into().ok()?);
+            ignored |= extended & 0x4000 != 0;
+            position += 2;
+        }
+        let path = if version == 4 {
+            // the number of bytes to drop from the previous path, then the
+            // rest of this one
+            let mut strip = 0usize;
+            loop {
+                let byte = *data.get(position)?;
+                position += 1;
+                strip = (strip << 7) | (byte & 0x7f) as usize;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+                strip += 1;
+            }
+            let end = position + data.get(position..)?.iter().position(|b| *b == 0)?;
+            let mut path = previous
+                .get(..previous.len().checked_sub(strip)?)?
+                .to_string();
+            path += &String::from_utf8_lossy(&data[position..end]);
+            position = end + 1;
+            path
+        } else {
+            let end = position + data.get(position..)?.iter().position(|b| *b == 0)?;
+            let path = String::from_utf8_lossy(&data[position..end]).to_string();
+            // entries are padded with 1 to 8 NULs to a multiple of 8 bytes
+            position = start + (end - start + 8) / 8 * 8;
+            path
+        };
+        previous = path.clone();
+        entries.push(IndexEntry {
+            path,
+            id,
+            mode,
+            mtime,
+            size,
+            stage: (flags >> 12) & 3,
+            ignored,
+        });
+    }
+    Some(entries)
+}
+
+/// Puts a commit in the queue of `ahead_behind`, ordered by time.
+fn enqueue(
+    store: &Store,
+    id: &str,
+    parents: &mut HashMap<String, Vec<String>>,
+    queue: &mut BinaryHeap<(i64, Reverse<String>)>,
+) -> Option<()> {
+    if !parents.contains_key(id) {
+        let (commit_parents, time) = store.commit(id)?;
+        parents.insert(id.to_string(), commit_parents);
+        queue.push((time, Reverse(id.to_string())));
+    }
+    Some(())
+}
+
+/// Counts the commits reachable from only one of `local` and `upstream`,
+/// walking both histories newest first until all that is left is shared.
+fn ahead_behind(
+    store: &Store,
+    local: &str,
+    upstream: &str,
+    budget: &Budget,
+) -> Option<(usize, usize)> {
+    const LOCAL: u8 = 1;
+    const UPSTREAM: u8 = 2;
+    const BOTH: u8 = LOCAL | UPSTREAM;
+    let mut flags: HashMap<String, u8> = HashMap::new();
+    // parents of the queued commits, read once along with their time
+    let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    for (id, flag) in [(local, LOCAL), (upstream, UPSTREAM)] {
+        *flags.entry(id.to_string()).or_default() |= flag;
+        enqueue(store, id, &mut parents, &mut queue)?;
+    }
+    // queued commits reached from one side only: once there are none
+    // left, the rest of the history is shared
+    let mut pending = flags.values().filter(|flag| **flag != BOTH).count();
+    let (mut ahead, mut behind) = (0, 0);
+    let mut done = HashSet::new();
+    while let Some((_, Reverse(id))) = queue.pop() {
+        if budget.exceeded() {
+            return None;
+        }
+        if !done.insert(id.clone()) {
+            continue;
+        }
+        let flag = flags[&id];
+        match flag {
+            LOCAL => ahead += 1,
+            UPSTREAM => behind += 1,
+            _ => (),
+        }
+        if flag != BOTH {
+            pending -= 1;
+        }
+        for parent in parents.get(&id).cloned().unwrap_or_default() {
+            let parent_flag = flags.entry(parent.clone()).or_default();
+            if *parent_flag | flag == *parent_flag {
+                continue;
+            }
+            *parent_flag |= flag;
+            let reached_both = *parent_flag == BOTH;
+            if !parents.contains_key(&parent) {
+                // a missing parent, as in a shallow clone, ends the walk there
+                if enqueue(store, &parent, &mut parents, &mut queue).is_some() && !reached_both {
+                    pending += 1;
+                }
+            } else if reached_both && !done.contains(&parent) {
+                pending -= 1;
+            }
+        }
+        if pending == 0 {
+            break;
+        }
+    }
+    Some((ahead, behind))
+}
+
+/// The segment for the repository around the working directory, empty
+/// outside of one.
+pub fn segment(budget: Duration) -> String {
+    let budget = Budget::new(budget);
+    std::env::current_dir()
+        .ok()
+        .and_then(|dir| Repository::discover(&dir))
+        .and_then(|repository| repository.status(&budget))
+        .map(|status| status.segment())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path, time::Duration};
+
+    use miniz_oxide::deflate::compress_to_vec_zlib;
+
+    use super::{ahead_behind, objects::Store, parse_index, Budget, Head, Repository, Status};
+
+    /// Writes a loose commit with fake ids made of a single repeated digit.
+    fn commit(objects: &Path, id: char, parents: &[char], time: u32) -> String {
+        let id = id.to_string().repeat(40);
+        let mut body = format!("tree {}\n", "0".repeat(40));
+        for parent in parents {
+            body += &format!("parent {}\n", parent.to_string().repeat(40));
+        }
+        body += &format!("committer a <a@b> {time} +0000\n\nmessage\n");
+        let object = format!("commit {}\0{body}", body.len());
+        let dir = objects.join(&id[..2]);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(&id[2..]),
+            compress_to_vec_zlib(object.as_bytes(), 6),
+        )
+        .unwrap();
+        id
+    }
+
+    #[test]
+    fn test_segment() {
+        let mut status = Status {
+            head: Head::Branch("main".to_string()),
+            ahead_behind: Some((0, 0)),
+            staged: Some(false),
+            dirty: Some(false),
+        };
+        assert_eq!(status.segment(), "(main)");
+        status.ahead_behind = Some((1, 2));
+        status.dirty = Some(true);
+        assert_eq!(status.segment(), "(main * ↑1↓2)");
+        status.head = Head::Detached("abc1234".to_string());
+        status.staged = None;
+        status.ahead_behind = None;
+        assert_eq!(status.segment(), "(@abc1234 ?*)");
+    }
+
+    #[test]
+    fn test_repository() {
+        let dir = std::env::temp_dir().join(format!("tinysh-git-{}", std::process::id()));
+        let git = dir.join(".git");
+        let objects = git.join("objects");
+        fs::create_dir_all(git.join("refs/heads")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        // 1 <- 2 <- 3 <- 4 on main, 2 <- 5 upstream
+        commit(&objects, '1', &[], 1);
+        commit(&objects, '2', &['1'], 2);
+        commit(&objects, '3', &['2'], 3);
+        let main = commit(&objects, '4', &['3'], 5);
+        let upstream = commit(&objects, '5', &['2'], 4);
+        fs::write(git.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(git.join("refs/heads/main"), format!("{main}\n")).unwrap();
+        fs::write(
+            git.join("packed-refs"),
+            format!("# pack-refs with: peeled\n{upstream} refs/remotes/origin/main\n"),
+        )
+        .unwrap();
+        fs::write(
+            git.join("config"),
+            "[core]\n\tbare = false\n[branch \"main\"]\n\tremote = origin\n\tmerge = refs/heads/main\n",
+        )
+        .unwrap();
+
+        let repository = Repository::discover(&dir.join("src")).unwrap();
+        assert_eq!(repository.head(), Some(Head::Branch("main".to_string())));
+        assert_eq!(repository.resolve("HEAD"), Some(main.clone()));
+        assert_eq!(
+            repository.upstream("main"),
+            Some("refs/remotes/origin/main".to_string())
+        );
+        assert_eq!(
+            repository.resolve("refs/remotes/origin/main"),
+            Some(upstream.clone())
+        );
+        assert_eq!(repository.branches(), vec!["main", "origin/main"]);
+        let store = Store::open(objects);
+        let budget = Budget::new(Duration::from_secs(5));
+        assert_eq!(
+            ahead_behind(&store, &main, &upstream, &budget),
+            Some((2, 1))
+        );
+        assert_eq!(ahead_behind(&store, &main, &main, &budget), Some((0, 0)));
+        let expired = Budget::new(Duration::ZERO);
+        assert_eq!(ahead_behind(&store, &main, &upstream, &expired), None);
+
+        fs::write(git.join("HEAD"), format!("{upstream}\n")).unwrap();
+        assert_eq!(
+            repository.head(),
+            Some(Head::Detached("5555555".to_string()))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_index() {
+        let mut index = b"DIRC\0\0\0\x02\0\0\0\x02".to_vec();
+        for (path, stage) in [("a.txt", 0u16), ("src/b.rs", 2)] {
+            let start = index.len();
+            let mut fields = [0u32; 10];
+            fields[2] = 7; // mtime
+            fields[6] = 0o100644; // mode
+            fields[9] = 3; // size
+            for field in fields {
+                index.extend(field.to_be_bytes());
+            }
+            index.extend([0xab; 20]);
+            index.extend(((stage << 12) | path.len() as u16).to_be_bytes());
+            index.extend(path.as_bytes());
+            index.push(0);
+            while !(index.len() - start).is_multiple_of(8) {
+                index.push(0);
+            }
+        }
+        let entries = parse_index(&index).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].path, "src/b.rs");
+        assert_eq!(entries[1].stage, 2);
+        assert_eq!(entries[0].mtime, (7, 0));
+        assert_eq!(entries[0].mode, 0o100644);
+        assert_eq!(entries[0].id, "ab".repeat(20));
+        assert!(parse_index(b"DIRC\0\0\0\x09").is_none());
+    }
+}
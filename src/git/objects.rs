@@ -0,0 +1,365 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use miniz_oxide::inflate::{decompress_to_vec_zlib, decompress_to_vec_zlib_with_limit};
+
+/// Pack types, as numbered in the header of a packed object.
+const COMMIT: u8 = 1;
+const TREE: u8 = 2;
+const BLOB: u8 = 3;
+const TAG: u8 = 4;
+const OFS_DELTA: u8 = 6;
+const REF_DELTA: u8 = 7;
+
+/// Deltas chained deeper than this are considered corrupted.
+const MAX_DELTA_DEPTH: u32 = 64;
+
+/// A pack and its `.idx`, read in place: packs of large repositories are
+/// too big to be loaded for every prompt.
+struct Pack {
+    index: File,
+    data: File,
+}
+
+/// Up to `length` bytes at `position`, fewer at the end of the file.
+fn read_bytes(file: &File, position: u64, length: usize) -> Option<Vec<u8>> {
+    let mut file = file;
+    file.seek(SeekFrom::Start(position)).ok()?;
+    let mut bytes = Vec::with_capacity(length);
+    file.take(length as u64).read_to_end(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+fn read_u32(file: &File, position: u64) -> Option<u32> {
+    let bytes = read_bytes(file, position, 4)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Start of the object ids in a version 2 index, after the header and the
+/// fan-out table.
+const IDS: u64 = 8 + 256 * 4;
+
+impl Pack {
+    fn open(idx: &Path) -> Option<Pack> {
+        let index = File::open(idx).ok()?;
+        // only the version 2 index with SHA-1 ids is supported
+        if read_bytes(&index, 0, 8)? != [0xff, b't', b'O', b'c', 0, 0, 0, 2] {
+            return None;
+        }
+        let data = File::open(idx.with_extension("pack")).ok()?;
+        if read_bytes(&data, 0, 4)? != b"PACK" {
+            return None;
+        }
+        Some(Pack { index, data })
+    }
+
+    /// Offset of an object in the pack, found by binary search in the
+    /// ids of the index between the fan-out bounds of its first byte.
+    fn offset(&self, id: &[u8; 20]) -> Option<u64> {
+        let fanout = |byte: u8| read_u32(&self.index, 8 + byte as u64 * 4);
+        let count = fanout(255)? as u64;
+        let mut low = match id[0] {
+            0 => 0,
+            byte => fanout(byte - 1)? as u64,
+        };
+        let mut high = fanout(id[0])? as u64;
+        while low < high {
+            let middle = (low + high) / 2;
+            let candidate = read_bytes(&self.index, IDS + middle * 20, 20)?;
+            match candidate.as_slice().cmp(&id[..]) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => {
+                    // ids, then a CRC and a 4-byte offset for each object
+                    let offsets = IDS + count * 24;
+                    let offset = read_u32(&self.index, offsets + middle * 4)?;
+                    if offset & 0x8000_0000 == 0 {
+                        return Some(offset as u64);
+                    }
+                    let large = offsets + count * 4 + (offset & 0x7fff_ffff) as u64 * 8;
+                    let bytes = read_bytes(&self.index, large, 8)?;
+                    return Some(u64::from_be_bytes(bytes.try_into().ok()?));
+                }
+            }
+        }
+        None
+    }
+
+    /// Decompresses data of a known size; zlib never makes it much larger.
+    /// The stream ends before the next object, and a corrupted one cannot
+    /// grow past its size.
+    fn decompress(&self, position: u64, size: usize) -> Option<Vec<u8>> {
+        let compressed = read_bytes(&self.data, position, size + size / 1024 + 64)?;
+        decompress_to_vec_zlib_with_limit(&compressed, size)
+            .ok()
+            .filter(|data| data.len() == size)
+    }
+
+    /// The object at `offset`, with its deltas applied.
+    fn read_at(&self, store: &Store, offset: u64, depth: u32) -> Option<(u8, Vec<u8>)> {
+        if depth > MAX_DELTA_DEPTH {
+            return None;
+        }
+        // type and size, then the base of a delta: at most 10 + 20 bytes
+        let header = read_bytes(&self.data, offset, 32)?;
+        let mut position = 0;
+        let mut byte = *header.get(position)?;
+        position += 1;
+        let kind = (byte >> 4) & 7;
+        let mut size = (byte & 0x0f) as usize;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = *header.get(position)?;
+            position += 1;
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+        }
+        match kind {
+            COMMIT | TREE | BLOB | TAG => {
+                Some((kind, self.decompress(offset + position as u64, size)?))
+            }
+            OFS_DELTA => {
+                let mut byte = *header.get(position)?;
+                position += 1;
+                let mut distance = (byte & 0x7f) as u64;
+                while byte & 0x80 != 0 {
+                    byte = *header.get(position)?;
+                    position += 1;
+                    distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+                }
+                let (kind, base) = self.read_at(store, offset.checked_sub(distance)?, depth + 1)?;
+                let delta = self.decompress(offset + position as u64, size)?;
+                Some((kind, apply_delta(&base, &delta)?))
+            }
+            REF_DELTA => {
+                let base_id: [u8; 20] = header.get(position..position + 20)?.try_into().ok()?;
+                let (kind, base) = store.read_raw(&base_id, depth + 1)?;
+                let delta = self.decompress(offset + position as u64 + 20, size)?;
+                Some((kind, apply_delta(&base, &delta)?))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn delta_size(delta: &[u8], position: &mut usize) -> Option<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta.get(*position)?;
+        *position += 1;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(size);
+        }
+    }
+}
+
+/// Rebuilds an object from its base and a delta of copy and insert
+/// instructions.
+fn apply_delta(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut position = 0;
+    if delta_size(delta, &mut position)? != base.len() {
+        return None;
+    }
+    let size = delta_size(delta, &mut position)?;
+    let mut result = Vec::with_capacity(size);
+    while let Some(&op) = delta.get(position) {
+        position += 1;
+        if op & 0x80 != 0 {
+            let mut offset = 0;
+            let mut length = 0;
+            for i in 0..7 {
+                if op & (1 << i) != 0 {
+                    let byte = *delta.get(position)? as usize;
+                    position += 1;
+                    if i < 4 {
+                        offset |= byte << (8 * i);
+                    } else {
+                        length |= byte << (8 * (i - 4));
+                    }
+                }
+            }
+            if length == 0 {
+                length = 0x10000;
+            }
+            result.extend_from_slice(base.get(offset..offset + length)?);
+        } else if op != 0 {
+            let length = op as usize;
+            result.extend_from_slice(delta.get(position..position + length)?);
+            position += length;
+        } else {
+            return None;
+        }
+    }
+    (result.len() == size).then_some(result)
+}
+
+/// The objects of a repository, loose or packed.
+pub struct Store {
+    objects: PathBuf,
+    packs: Vec<Pack>,
+}
+
+impl Store {
+    pub fn open(objects: PathBuf) -> Store {
+        let mut packs = Vec::new();
+        if let Ok(entries) = fs::read_dir(objects.join("pack")) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|e| e == "idx") {
+                    packs.extend(Pack::open(&path));
+                }
+            }
+        }
+        Store { objects, packs }
+    }
+
+    fn read_raw(&self, id: &[u8; 20], depth: u32) -> Option<(u8, Vec<u8>)> {
+        let hex = to_hex(id);
+        if let Ok(compressed) = fs::read(self.objects.join(&hex[..2]).join(&hex[2..])) {
+            let data = decompress_to_vec_zlib(&compressed).ok()?;
+            let header_end = data.iter().position(|b| *b == 0)?;
+            let kind = match data[..header_end].split(|b| *b == b' ').next()? {
+                b"commit" => COMMIT,
+                b"tree" => TREE,
+                b"blob" => BLOB,
+                b"tag" => TAG,
+                _ => return None,
+            };
+            return Some((kind, data[header_end + 1..].to_vec()));
+        }
+        self.packs
+            .iter()
+            .find_map(|pack| pack.read_at(self, pack.offset(id)?, depth))
+    }
+
+    fn read(&self, id: &str, kind: u8) -> Option<Vec<u8>> {
+        let (found, data) = self.read_raw(&from_hex(id)?, 0)?;
+        (found == kind).then_some(data)
+    }
+
+    /// The parents and the committer time of a commit.
+    pub fn commit(&self, id: &str) -> Option<(Vec<String>, i64)> {
+        let data = self.read(id, COMMIT)?;
+        let text = String::from_utf8_lossy(&data);
+        let mut parents = Vec::new();
+        let mut time = 0;
+        for line in text.lines() {
+            if line.is_empty() {
+                break;
+            }
+            if let Some(parent) = line.strip_prefix("parent ") {
+                parents.push(parent.to_string());
+            } else if let Some(committer) = line.strip_prefix("committer ") {
+                // `name <email> time zone`
+                time = committer
+                    .rsplit(' ')
+                    .nth(1)
+                    .and_then(|t| t.parse().ok())
+                    .unwrap_or_default();
+            }
+        }
+        Some((parents, time))
+    }
+
+    /// The tree a commit points to.
+    pub fn commit_tree(&self, id: &str) -> Option<String> {
+        let data = self.read(id, COMMIT)?;
+        let line = data.split(|b| *b == b'\n').next()?;
+        Some(String::from_utf8_lossy(line.strip_prefix(b"tree ")?).to_string())
+    }
+
+    /// The entries of a tree as `(mode, name, id)`.
+    pub fn tree(&self, id: &str) -> Option<Vec<(u32, String, String)>> {
+        let data = self.read(id, TREE)?;
+        let mut entries = Vec::new();
+        let mut rest = &data[..];
+        while !rest.is_empty() {
+            let space = rest.iter().position(|b| *b == b' ')?;
+            let nul = rest.iter().position(|b| *b == 0)?;
+            let mode = u32::from_str_radix(std::str::from_utf8(&rest[..space]).ok()?, 8).ok()?;
+            let name = String::from_utf8_lossy(&rest[space + 1..nul]).to_string();
+            let id = to_hex(rest.get(nul + 1..nul + 21)?);
+            entries.push((mode, name, id));
+            rest = &rest[nul + 21..];
+        }
+        Some(entries)
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut bytes = [0u8; 20];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use miniz_oxide::deflate::compress_to_vec_zlib;
+
+    use super::{apply_delta, from_hex, to_hex, Store};
+
+    #[test]
+    fn test_hex() {
+        let id = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(to_hex(&from_hex(id).unwrap()), id);
+        assert_eq!(from_hex("0123"), None);
+        assert_eq!(from_hex(&"g".repeat(40)), None);
+    }
+
+    #[test]
+    fn test_delta() {
+        let base = b"hello world";
+        // base size 11, result size 12, copy 6 bytes at 0, insert "there!"
+        let delta = [11, 12, 0x90, 6, 6, b't', b'h', b'e', b'r', b'e', b'!'];
+        assert_eq!(apply_delta(base, &delta), Some(b"hello there!".to_vec()));
+        assert_eq!(apply_delta(b"short", &delta), None);
+        for end in 0..delta.len() {
+            assert_eq!(apply_delta(base, &delta[..end]), None);
+        }
+    }
+
+    #[test]
+    fn test_corrupt_objects() {
+        let objects = std::env::temp_dir().join(format!("tinysh-objects-{}", std::process::id()));
+        let object = b"commit 48\0parent 1111111111111111111111111111111111111111\n";
+        let compressed = compress_to_vec_zlib(object, 6);
+        let mut corrupted = compressed.clone();
+        corrupted[4] ^= 0xff;
+        let mut ids = Vec::new();
+        for (digit, data) in [
+            ('a', compressed.clone()),
+            ('b', compressed[..compressed.len() - 6].to_vec()),
+            ('c', corrupted),
+            ('d', b"not zlib".to_vec()),
+            ('e', Vec::new()),
+        ] {
+            let id = digit.to_string().repeat(40);
+            fs::create_dir_all(objects.join(&id[..2])).unwrap();
+            fs::write(objects.join(&id[..2]).join(&id[2..]), data).unwrap();
+            ids.push(id);
+        }
+        let store = Store::open(objects.clone());
+        assert_eq!(store.commit(&ids[0]), Some((vec!["1".repeat(40)], 0)));
+        for id in &ids[1..] {
+            assert_eq!(store.commit(id), None);
+        }
+        fs::remove_dir_all(objects).unwrap();
+    }
+}
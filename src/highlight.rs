@@ -0,0 +1,380 @@
+//! Colours of the line being typed, from its tokens: commands green when
+//! they exist and red otherwise, keywords, strings, operators, variables
+//! and comments each in their own colour, and unmatched quotes and
+//! parentheses as errors.
+//!
+//! The read loop draws the coloured line over the one `linefeed` shows,
+//! after each key, the same way as the suggestions.
+
+use std::{io::Write, ops::Range};
+
+use ansi_term::{Color, Style};
+
+use crate::{
+    interpreting::interpreter::is_a_valid_name,
+    lexing::{
+        lexer::Lexer,
+        token::{Keyword, SpannedToken, Token},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    Plain,
+    Command,
+    UnknownCommand,
+    Keyword,
+    String,
+    Operator,
+    Variable,
+    Comment,
+    Error,
+}
+
+impl Highlight {
+    fn style(self) -> Style {
+        match self {
+            Highlight::Plain => Style::new(),
+            Highlight::Command => Color::Green.normal(),
+            Highlight::UnknownCommand => Color::Red.normal(),
+            Highlight::Keyword => Color::Purple.bold(),
+            Highlight::String => Color::Yellow.normal(),
+            Highlight::Operator => Color::Cyan.normal(),
+            Highlight::Variable => Color::Blue.bold(),
+            Highlight::Comment => Color::Fixed(244).italic(),
+            Highlight::Error => Color::White.on(Color::Red),
+        }
+    }
+}
+
+/// The highlight of each byte of `text`; `is_command` tells whether the
+/// word a command starts with names one.
+pub fn highlights(text: &str, is_command: impl Fn(&str) -> bool) -> Vec<Highlight> {
+    let tokens = Lexer {
+        str: text.to_string(),
+    }
+    .lex_spanned();
+    let mut marks = vec![Highlight::Plain; text.len()];
+    let mut lexed = vec![false; text.len()];
+    for token in &tokens {
+        lexed[token.span.start..token.span.end].fill(true);
+    }
+    // the lexer skips comments, they are what is left from an unquoted `#`
+    let mut i = 0;
+    while i < text.len() {
+        if !lexed[i] && text.as_bytes()[i] == b'#' {
+            let end = text[i..].find('\n').map_or(text.len(), |n| i + n);
+            marks[i..end].fill(Highlight::Comment);
+            i = end;
+        }
+        i += 1;
+    }
+
+    // whether a command can start at the next word, and whether that word
+    // is the file of a redirection instead
+    let mut command = true;
+    let mut redirected = false;
+    let mut open = Vec::new();
+    let mut cases = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let span = tokens[i].span;
+        match &tokens[i].token {
+            Token::Pipe | Token::And | Token::Or | Token::Semicolon | Token::CaseEnd(_) => {
+                marks[span.start..span.end].fill(Highlight::Operator);
+                command = true;
+            }
+            Token::LeftRedirection | Token::RightRedirection => {
+                marks[span.start..span.end].fill(Highlight::Operator);
+                redirected = true;
+            }
+            Token::LPar | Token::LBrace => {
+                marks[span.start..span.end].fill(Highlight::Operator);
+                open.push(span.start);
+                command = true;
+            }
+            Token::RPar | Token::RBrace => {
+                // `)` also ends the patterns of a `case` arm, before its
+                // commands
+                let closes = open.pop().is_some();
+                let pattern = !closes && tokens[i].token == Token::RPar && cases > 0;
+                marks[span.start..span.end].fill(match closes || pattern {
+                    true => Highlight::Operator,
+                    false => Highlight::Error,
+                });
+                command = pattern;
+            }
+            Token::Keyword(keyword)
+                if (command && *keyword != Keyword::Not) || *keyword == Keyword::CloseTest =>
+            {
+                marks[span.start..span.end].fill(Highlight::Keyword);
+                match keyword {
+                    Keyword::Case => cases += 1,
+                    Keyword::Esac => cases -= usize::from(cases > 0),
+                    _ => (),
+                }
+                command = keyword.opens_list();
+            }
+            _ => {
+                let (range, next) = word(text, &tokens, i, &mut marks);
+                let name = text[range.clone()].replace('"', "");
+                let assignment = name
+                    .split_once('=')
+                    .is_some_and(|(name, _)| is_a_valid_name(name));
+                if command && !redirected && !assignment && !name.contains('$') {
+                    let highlight = match is_command(&name) {
+                        true => Highlight::Command,
+                        false => Highlight::UnknownCommand,
+                    };
+                    marks[range.clone()].fill(highlight);
+                }
+                mark_variables(text, range, &mut marks);
+                command &= redirected || assignment;
+                redirected = false;
+                i = next;
+            }
+        }
+        i += 1;
+    }
+    for start in open {
+        marks[start] = Highlight::Error;
+    }
+    marks
+}
+
+/// The bytes of the word starting at `tokens[i]`, its quoted parts marked
+/// as strings, and the index of its last token. A quote left open makes
+/// the rest of the text an error.
+fn word(
+    text: &str,
+    tokens: &[SpannedToken],
+    mut i: usize,
+    marks: &mut [Highlight],
+) -> (Range<usize>, usize) {
+    let start = tokens[i].span.start;
+    loop {
+        let mut end = tokens[i].span.end;
+        if tokens[i].token == Token::Quote {
+            match (i + 1..tokens.len()).find(|&j| tokens[j].token == Token::Quote) {
+                Some(close) => {
+                    end = tokens[close].span.end;
+                    marks[tokens[i].span.start..end].fill(Highlight::String);
+                    i = close;
+                }
+                None => {
+                    marks[tokens[i].span.start..].fill(Highlight::Error);
+                    return (start..text.len(), tokens.len());
+                }
+            }
+        }
+        match tokens.get(i + 1) {
+            Some(next) if next.span.start == end && is_a_word_token(&next.token) => i += 1,
+            _ => return (start..end, i),
+        }
+    }
+}
+
+fn is_a_word_token(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Int(_)
+            | Token::Float(_)
+            | Token::Identifier(_)
+            | Token::Bool(_)
+            | Token::Quote
+            | Token::Tilde
+            | Token::Dash
+            | Token::Keyword(_)
+    )
+}
+
+/// Marks `$NAME`, `${NAME}` and the special parameters in `range`.
+fn mark_variables(text: &str, range: Range<usize>, marks: &mut [Highlight]) {
+    let bytes = text.as_bytes();
+    let mut i = range.start;
+    while i < range.end {
+        if bytes[i] == b'\\' {
+            // `\$` is not a variable
+            i += 2;
+            continue;
+        }
+        if bytes[i] != b'$' || i + 1 >= range.end {
+            i += 1;
+            continue;
+        }
+        let end = match bytes[i + 1] {
+            b'{' => text[i..range.end]
+                .find('}')
+                .map_or(range.end, |n| i + n + 1),
+            b'?' | b'#' | b'@' | b'*' | b'0'..=b'9' => i + 2,
+            b if b.is_ascii_alphabetic() || b == b'_' => text[i + 1..range.end]
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .map_or(range.end, |n| i + 1 + n),
+            _ => i + 1,
+        };
+        // inside a quote left open, the error shows
+        if end > i + 1 && marks[i] != Highlight::Error {
+            marks[i..end].fill(Highlight::Variable);
+        }
+        i = end;
+    }
+}
+
+/// `text` with the escapes of its highlights.
+pub fn paint(text: &str, marks: &[Highlight]) -> String {
+    let mut painted = String::new();
+    let mut start = 0;
+    for end in 1..=text.len() {
+        if end == text.len() || marks[end] != marks[start] {
+            painted += &marks[start].style().paint(&text[start..end]).to_string();
+            start = end;
+        }
+    }
+    painted
+}
+
+/// Writes `painted` over the line shown from `column`, then puts the
+/// cursor back.
+pub fn draw(column: usize, painted: &str) {
+    print!("\x1b7\x1b[{column}G{painted}\x1b8");
+    let _ = std::io::stdout().flush();
+}
+
+#[cfg(test)]
+mod test {
+    use super::{highlights, paint, Highlight};
+
+    /// The highlight of each part of `text`, as (text, highlight) pairs.
+    fn parts(text: &str) -> Vec<(String, Highlight)> {
+        let marks = highlights(text, |name| ["echo", "ls", "cd"].contains(&name));
+        let mut parts: Vec<(String, Highlight)> = Vec::new();
+        for (i, c) in text.char_indices() {
+            match parts.last_mut() {
+                Some((part, mark)) if *mark == marks[i] => part.push(c),
+                _ => parts.push((c.to_string(), marks[i])),
+            }
+        }
+        parts
+            .into_iter()
+            .filter(|(part, _)| !part.trim().is_empty())
+            .map(|(part, mark)| (part.trim().to_string(), mark))
+            .collect()
+    }
+
+    fn part(text: &str, highlight: Highlight) -> (String, Highlight) {
+        (text.to_string(), highlight)
+    }
+
+    #[test]
+    fn test_commands() {
+        use Highlight::*;
+        assert_eq!(
+            parts("ls -l | grep x && cd"),
+            vec![
+                part("ls", Command),
+                part("-l", Plain),
+                part("|", Operator),
+                part("grep", UnknownCommand),
+                part("x", Plain),
+                part("&&", Operator),
+                part("cd", Command),
+            ]
+        );
+        assert_eq!(
+            parts("X=1 echo > ls"),
+            vec![
+                part("X=1", Plain),
+                part("echo", Command),
+                part(">", Operator),
+                part("ls", Plain),
+            ]
+        );
+        assert_eq!(
+            parts("if ls; then echo if; fi"),
+            vec![
+                part("if", Keyword),
+                part("ls", Command),
+                part(";", Operator),
+                part("then", Keyword),
+                part("echo", Command),
+                part("if", Plain),
+                part(";", Operator),
+                part("fi", Keyword),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_words() {
+        use Highlight::*;
+        assert_eq!(
+            parts("echo \"$HOME is\" $1${X}y # done"),
+            vec![
+                part("echo", Command),
+                part("\"", String),
+                part("$HOME", Variable),
+                part("is\"", String),
+                part("$1${X}", Variable),
+                part("y", Plain),
+                part("# done", Comment),
+            ]
+        );
+        assert_eq!(
+            parts("echo \\$a $b"),
+            vec![
+                part("echo", Command),
+                part("\\$a", Plain),
+                part("$b", Variable)
+            ]
+        );
+        assert_eq!(
+            parts("echo \"a # b\" 99999999999999999999"),
+            vec![
+                part("echo", Command),
+                part("\"a # b\"", String),
+                part("99999999999999999999", Plain),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        use Highlight::*;
+        assert_eq!(
+            parts("echo \"a ($b"),
+            vec![part("echo", Command), part("\"a ($b", Error)]
+        );
+        assert_eq!(
+            parts("(ls)) (echo"),
+            vec![
+                part("(", Operator),
+                part("ls", Command),
+                part(")", Operator),
+                part(")", Error),
+                part("(", Error),
+                part("echo", Command),
+            ]
+        );
+        assert_eq!(
+            parts("case x in a) ls;; esac"),
+            vec![
+                part("case", Keyword),
+                part("x in a", Plain),
+                part(")", Operator),
+                part("ls", Command),
+                part(";;", Operator),
+                part("esac", Keyword),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paint() {
+        let text = "ls | x";
+        let marks = highlights(text, |name| name == "ls");
+        assert_eq!(
+            paint(text, &marks),
+            "\x1b[32mls\x1b[0m \x1b[36m|\x1b[0m \x1b[31mx\x1b[0m"
+        );
+    }
+}
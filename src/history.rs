@@ -0,0 +1,418 @@
+//! The command history of the prompt, kept across sessions in
+//! `$XDG_STATE_HOME/tinysh/history` (`~/.local/state` by default).
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::interpreting::{expansion::matches_glob, interpreter::Context};
+
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    pub entries: Vec<String>,
+    /// Number of entries at the end of `entries` not written to the file yet
+    unsaved: usize,
+    /// Set by `history -c` and `history -d` so that the prompt drops the
+    /// removed entries too
+    edited: bool,
+}
+
+/// `$HISTFILE`, or the `tinysh/history` file of the XDG state directory.
+pub fn history_file(ctx: &Context) -> Option<PathBuf> {
+    if let Some(file) = ctx.get_variable("HISTFILE").filter(|f| !f.is_empty()) {
+        return Some(PathBuf::from(file));
+    }
+    let state = match ctx.get_variable("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(ctx.get_variable("HOME")?).join(".local/state"),
+    };
+    Some(state.join("tinysh").join("history"))
+}
+
+/// Whether a line stays out of the history: a blank line, one starting
+/// with a space, or one matching a pattern of the `:`-separated
+/// `$HISTIGNORE`, where `&` stands for the previous entry.
+pub fn is_ignored(line: &str, ignore: &str, previous: Option<&str>) -> bool {
+    if line.trim().is_empty() || line.starts_with(' ') {
+        return true;
+    }
+    ignore
+        .split(':')
+        .filter(|pattern| !pattern.is_empty())
+        .any(|pattern| match pattern {
+            "&" => previous == Some(line),
+            pattern => matches_glob(pattern, line),
+        })
+}
+
+/// Entries span several lines when they were continued, so the file
+/// escapes newlines and backslashes to keep one entry per line.
+fn encode(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn decode(line: &str) -> String {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                entry.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                entry.push('\\');
+                chars.next();
+            }
+            (c, _) => entry.push(c),
+        }
+    }
+    entry
+}
+
+/// Waits until no other session writes the file; the lock goes with it.
+#[cfg(unix)]
+fn lock(file: &File, exclusive: bool) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let operation = if exclusive {
+        libc::LOCK_EX
+    } else {
+        libc::LOCK_SH
+    };
+    match unsafe { libc::flock(file.as_raw_fd(), operation) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_file: &File, _exclusive: bool) -> io::Result<()> {
+    Ok(())
+}
+
+fn read_entries(file: &mut File) -> io::Result<Vec<String>> {
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents.lines().map(decode).collect())
+}
+
+fn keep_last(entries: &mut Vec<String>, size: usize) {
+    if entries.len() > size {
+        entries.drain(..entries.len() - size);
+    }
+}
+
+impl History {
+    /// Reads the last `size` entries of the file; a missing file is an
+    /// empty history.
+    pub fn load(&mut self, path: &Path, size: usize) -> io::Result<()> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        lock(&file, false)?;
+        self.entries = read_entries(&mut file)?;
+        keep_last(&mut self.entries, size);
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    /// Records a line unless it is ignored or repeats the last entry, and
+    /// tells whether it did.
+    pub fn add(&mut self, line: &str, ignore: &str, size: usize) -> bool {
+        let previous = self.entries.last().map(|e| e.as_str());
+        if is_ignored(line, ignore, previous) || previous == Some(line) {
+            return false;
+        }
+        self.entries.push(line.to_string());
+        self.unsaved += 1;
+        keep_last(&mut self.entries, size);
+        self.unsaved = self.unsaved.min(self.entries.len());
+        true
+    }
+
+    /// Forgets every entry, which the file keeps as in bash.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.unsaved = 0;
+        self.edited = true;
+    }
+
+    /// Removes the entry at `index`, counted from 0.
+    pub fn delete(&mut self, index: usize) -> bool {
+        if index >= self.entries.len() {
+            return false;
+        }
+        if index >= self.entries.len() - self.unsaved {
+            self.unsaved -= 1;
+        }
+        self.entries.remove(index);
+        self.edited = true;
+        true
+    }
+
+    /// Whether entries were removed since the last call.
+    pub fn take_edited(&mut self) -> bool {
+        std::mem::take(&mut self.edited)
+    }
+
+    /// Appends the new entries to what the file has now, so that sessions
+    /// running side by side each add their own lines instead of
+    /// overwriting those of the others.
+    pub fn save(&mut self, path: &Path, size: usize) -> io::Result<()> {
+        if self.unsaved == 0 {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        lock(&file, true)?;
+        let mut entries = read_entries(&mut file)?;
+        entries.extend_from_slice(&self.entries[self.entries.len() - self.unsaved..]);
+        keep_last(&mut entries, size);
+        let contents: String = entries.iter().map(|e| encode(e) + "\n").collect();
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(contents.as_bytes())?;
+        self.unsaved = 0;
+        Ok(())
+    }
+}
+
+/// The words of an entry for `!$` and `!*`, double-quoted strings kept
+/// whole.
+fn words(entry: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in entry.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Where a `!prefix` event stops.
+fn ends_event(c: char) -> bool {
+    c.is_whitespace() || matches!(c, ';' | '|' | '&' | '(' | ')' | '<' | '>' | '"' | ':')
+}
+
+/// Replaces the csh-style events of a line with what they stand for:
+/// `!!` the previous entry, `!n` entry `n` of `history`, `!-n` the `n`th
+/// last one, `!prefix` the last one starting with `prefix`, `!$` and `!*`
+/// the last and all but the first words of the previous entry, and a line
+/// `^old^new` the previous entry with `old` replaced once by `new`.
+/// Returns `None` when the line has no event.
+pub fn expand_history(line: &str, entries: &[String]) -> Result<Option<String>, String> {
+    if let Some(rest) = line.strip_prefix('^') {
+        let mut parts = rest.splitn(3, '^');
+        let old = parts.next().unwrap_or_default();
+        let new = parts.next().unwrap_or_default();
+        return match entries.last() {
+            Some(previous) if !old.is_empty() && previous.contains(old) => Ok(Some(
+                previous.replacen(old, new, 1) + parts.next().unwrap_or_default(),
+            )),
+            _ => Err(format!("^{old}^{new}: substitution failed")),
+        };
+    }
+    let chars: Vec<char> = line.chars().collect();
+    let mut expanded = String::new();
+    let mut changed = false;
+    let (mut single, mut double) = (false, false);
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\'' if !double => single = !single,
+            '"' if !single => double = !double,
+            _ => (),
+        }
+        // `\!`, `$!`, `${!name}`, `! command`, `!=`, `!(`, `"!"` and
+        // anything in single quotes are left alone
+        let escaped = i > 0 && matches!(chars[i - 1], '\\' | '$' | '{');
+        let next = chars.get(i + 1).copied();
+        if c != '!'
+            || single
+            || escaped
+            || next.is_none_or(|n| n.is_whitespace() || matches!(n, '=' | '(' | '"'))
+        {
+            expanded.push(c);
+            i += 1;
+            continue;
+        }
+        let previous = entries.last();
+        let (event, length) = match next {
+            Some('!') => (previous.cloned(), 2),
+            Some('$') => (previous.and_then(|p| words(p).pop()), 2),
+            Some('*') => (
+                previous.map(|p| words(p).get(1..).unwrap_or_default().join(" ")),
+                2,
+            ),
+            _ => {
+                let length = chars[i + 1..]
+                    .iter()
+                    .position(|c| ends_event(*c))
+                    .unwrap_or(chars.len() - i - 1);
+                let text: String = chars[i + 1..i + 1 + length].iter().collect();
+                let event = match text.parse::<i64>() {
+                    Ok(n) if n < 0 => entries.len().checked_sub(n.unsigned_abs() as usize),
+                    Ok(n) => (n as usize).checked_sub(1),
+                    Err(_) => entries.iter().rposition(|e| e.starts_with(&text)),
+                };
+                (
+                    event.and_then(|index| entries.get(index)).cloned(),
+                    length + 1,
+                )
+            }
+        };
+        match event {
+            Some(event) => expanded += &event,
+            None => {
+                let text: String = chars[i..i + length].iter().collect();
+                return Err(format!("{text}: event not found"));
+            }
+        }
+        changed = true;
+        i += length;
+    }
+    Ok(changed.then_some(expanded))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreting::interpreter::Context;
+
+    use super::{decode, encode, expand_history, history_file, is_ignored, History};
+
+    #[test]
+    fn test_ignored() {
+        assert!(is_ignored(" secret", "", None));
+        assert!(is_ignored("  ", "", None));
+        assert!(!is_ignored("ls", "", None));
+        assert!(is_ignored("ls", "cd *:ls", None));
+        assert!(is_ignored("cd /tmp", "cd *:ls", None));
+        assert!(!is_ignored("lsblk", "cd *:ls", None));
+        assert!(is_ignored("echo", "&", Some("echo")));
+        assert!(!is_ignored("echo", "&", Some("ls")));
+    }
+
+    #[test]
+    fn test_encoding() {
+        let entry = "for i in 1 2\ndo echo a\\b\ndone";
+        assert_eq!(encode(entry), "for i in 1 2\\ndo echo a\\\\b\\ndone");
+        assert_eq!(decode(&encode(entry)), entry);
+        assert_eq!(decode("a\\b"), "a\\b");
+    }
+
+    #[test]
+    fn test_file() {
+        let mut ctx = Context::new();
+        ctx.variables
+            .insert("HOME".to_string(), "/home/me".to_string());
+        ctx.variables
+            .insert("XDG_STATE_HOME".to_string(), String::new());
+        assert_eq!(
+            history_file(&ctx),
+            Some("/home/me/.local/state/tinysh/history".into())
+        );
+        ctx.variables
+            .insert("HISTFILE".to_string(), "/tmp/h".to_string());
+        assert_eq!(history_file(&ctx), Some("/tmp/h".into()));
+    }
+
+    #[test]
+    fn test_sessions_merge() {
+        let dir = std::env::temp_dir().join(format!("tinysh-history-{}", std::process::id()));
+        let path = dir.join("history");
+        let mut first = History::default();
+        let mut second = History::default();
+        assert!(first.add("ls", "", 3));
+        assert!(!first.add("ls", "", 3));
+        assert!(second.add("pwd", "", 3));
+        first.save(&path, 3).unwrap();
+        second.save(&path, 3).unwrap();
+        assert!(first.add("echo a\nb", "", 3));
+        assert!(first.add("date", "", 3));
+        first.save(&path, 3).unwrap();
+
+        let mut loaded = History::default();
+        loaded.load(&path, 10).unwrap();
+        assert_eq!(loaded.entries, vec!["pwd", "echo a\nb", "date"]);
+        loaded.load(&path, 1).unwrap();
+        assert_eq!(loaded.entries, vec!["date"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_expand_history() {
+        let entries: Vec<String> = ["ls -l", "echo \"a b\" c", "cat file.txt"]
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        let expand = |line: &str| expand_history(line, &entries);
+        assert_eq!(expand("echo hi"), Ok(None));
+        assert_eq!(expand("sudo !!"), Ok(Some("sudo cat file.txt".to_string())));
+        assert_eq!(expand("!1 /"), Ok(Some("ls -l /".to_string())));
+        assert_eq!(expand("!-2"), Ok(Some("echo \"a b\" c".to_string())));
+        assert_eq!(
+            expand("!ec;!l"),
+            Ok(Some("echo \"a b\" c;ls -l".to_string()))
+        );
+        assert_eq!(expand("vim !$"), Ok(Some("vim file.txt".to_string())));
+        assert_eq!(expand("echo !*"), Ok(Some("echo file.txt".to_string())));
+        assert_eq!(expand("^cat^less"), Ok(Some("less file.txt".to_string())));
+        assert_eq!(expand("^file^dir^/"), Ok(Some("cat dir.txt/".to_string())));
+        assert!(expand("^nope^x").is_err());
+        assert_eq!(expand("!9"), Err("!9: event not found".to_string()));
+        assert_eq!(expand("!zz"), Err("!zz: event not found".to_string()));
+        assert_eq!(expand("[ a != b ] && ! true; echo $! \\!x"), Ok(None));
+        assert_eq!(
+            expand("echo \"wow!\" 'a!b' \"'\"!!"),
+            Ok(Some("echo \"wow!\" 'a!b' \"'\"cat file.txt".to_string()))
+        );
+        assert_eq!(
+            expand("echo \"!!\""),
+            Ok(Some("echo \"cat file.txt\"".to_string()))
+        );
+        assert_eq!(
+            expand_history("!!", &[]),
+            Err("!!: event not found".to_string())
+        );
+    }
+
+    #[test]
+    fn test_clear_and_delete() {
+        let mut history = History::default();
+        history.add("a", "", 10);
+        history.add("b", "", 10);
+        assert!(!history.take_edited());
+        assert!(history.delete(0));
+        assert!(!history.delete(1));
+        assert_eq!(history.entries, vec!["b"]);
+        assert!(history.take_edited());
+        history.clear();
+        assert!(history.entries.is_empty());
+    }
+}
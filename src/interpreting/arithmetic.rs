@@ -0,0 +1,340 @@
+use super::interpreter::Context;
+
+/// Integer arithmetic as found in `for ((...))`: C operators with their
+/// usual precedence, variables read from and assigned to the context.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(i64),
+    Variable(String),
+    Unary(String, Box<Expr>),
+    Binary(String, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `name op= value`, with `op` empty for a plain `=`
+    Assign(String, String, Box<Expr>),
+    /// `++name` / `--name` when `prefix`, `name++` / `name--` otherwise
+    Increment(String, i64, bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ArithmeticToken {
+    Number(i64),
+    Name(String),
+    Operator(String),
+    LPar,
+    RPar,
+}
+
+const OPERATORS: [&str; 28] = [
+    "++", "--", "+=", "-=", "*=", "/=", "%=", "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*",
+    "/", "%", "<", ">", "=", "!", "?", ":", "&", "|", "^", "~",
+];
+
+fn tokenize(str: &str) -> Result<Vec<ArithmeticToken>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = str.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let n = rest[..end]
+                .parse()
+                .map_err(|_| format!("{} is too large", &rest[..end]))?;
+            tokens.push(ArithmeticToken::Number(n));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let name = rest.trim_start_matches('$');
+            let end = name
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(name.len());
+            tokens.push(ArithmeticToken::Name(name[..end].to_string()));
+            rest = &name[end..];
+        } else if c == '(' || c == ')' {
+            tokens.push(if c == '(' {
+                ArithmeticToken::LPar
+            } else {
+                ArithmeticToken::RPar
+            });
+            rest = &rest[1..];
+        } else {
+            match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+                Some(op) => {
+                    tokens.push(ArithmeticToken::Operator(op.to_string()));
+                    rest = &rest[op.len()..];
+                }
+                None => return Err(format!("unexpected character `{c}`")),
+            }
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Binding power of binary operators, higher binds tighter.
+fn precedence(op: &str) -> Option<u8> {
+    match op {
+        "||" => Some(1),
+        "&&" => Some(2),
+        "|" => Some(3),
+        "^" => Some(4),
+        "&" => Some(5),
+        "==" | "!=" => Some(6),
+        "<" | "<=" | ">" | ">=" => Some(7),
+        "+" | "-" => Some(8),
+        "*" | "/" | "%" => Some(9),
+        _ => None,
+    }
+}
+
+struct ArithmeticParser {
+    tokens: Vec<ArithmeticToken>,
+    position: usize,
+}
+
+impl ArithmeticParser {
+    fn peek(&self) -> Option<&ArithmeticToken> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<ArithmeticToken> {
+        let t = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        t
+    }
+
+    fn peek_operator(&self) -> Option<String> {
+        match self.peek() {
+            Some(ArithmeticToken::Operator(op)) => Some(op.clone()),
+            _ => None,
+        }
+    }
+
+    fn parse_assignment(&mut self) -> Result<Expr, String> {
+        if let (Some(ArithmeticToken::Name(name)), Some(ArithmeticToken::Operator(op))) = (
+            self.tokens.get(self.position),
+            self.tokens.get(self.position + 1),
+        ) {
+            if op == "="
+                || (op.len() == 2
+                    && op.ends_with('=')
+                    && !["==", "!=", "<=", ">="].contains(&op.as_str()))
+            {
+                let name = name.clone();
+                let op = op.trim_end_matches('=').to_string();
+                self.position += 2;
+                let value = self.parse_assignment()?;
+                return Ok(Expr::Assign(name, op, Box::from(value)));
+            }
+        }
+        self.parse_ternary()
+    }
+
+    fn parse_ternary(&mut self) -> Result<Expr, String> {
+        let condition = self.parse_binary(1)?;
+        if self.peek_operator().as_deref() != Some("?") {
+            return Ok(condition);
+        }
+        self.next();
+        let then = self.parse_assignment()?;
+        if self.next() != Some(ArithmeticToken::Operator(":".to_string())) {
+            return Err("expected `:`".to_string());
+        }
+        let otherwise = self.parse_assignment()?;
+        Ok(Expr::Conditional(
+            Box::from(condition),
+            Box::from(then),
+            Box::from(otherwise),
+        ))
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut left = self.parse_unary()?;
+        while let Some(op) = self.peek_operator() {
+            let Some(p) = precedence(&op) else {
+                break;
+            };
+            if p < min_precedence {
+                break;
+            }
+            self.next();
+            let right = self.parse_binary(p + 1)?;
+            left = Expr::Binary(op, Box::from(left), Box::from(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.peek_operator().as_deref() {
+            Some(op @ ("++" | "--")) => {
+                let step = if op == "++" { 1 } else { -1 };
+                self.next();
+                match self.next() {
+                    Some(ArithmeticToken::Name(name)) => Ok(Expr::Increment(name, step, true)),
+                    _ => Err(format!("`{op}` needs a variable")),
+                }
+            }
+            Some(op @ ("!" | "-" | "+" | "~")) => {
+                let op = op.to_string();
+                self.next();
+                Ok(Expr::Unary(op, Box::from(self.parse_unary()?)))
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(ArithmeticToken::Number(n)) => Ok(Expr::Number(n)),
+            Some(ArithmeticToken::Name(name)) => match self.peek_operator().as_deref() {
+                Some(op @ ("++" | "--")) => {
+                    let step = if op == "++" { 1 } else { -1 };
+                    self.next();
+                    Ok(Expr::Increment(name, step, false))
+                }
+                _ => Ok(Expr::Variable(name)),
+            },
+            Some(ArithmeticToken::LPar) => {
+                let expr = self.parse_assignment()?;
+                match self.next() {
+                    Some(ArithmeticToken::RPar) => Ok(expr),
+                    _ => Err("expected `)`".to_string()),
+                }
+            }
+            Some(ArithmeticToken::Operator(op)) => Err(format!("unexpected `{op}`")),
+            Some(ArithmeticToken::RPar) => Err("unexpected `)`".to_string()),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn variable(ctx: &Context, name: &str) -> i64 {
+    ctx.get_variable(name)
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn apply(op: &str, a: i64, b: i64) -> Result<i64, String> {
+    Ok(match op {
+        "+" => a.wrapping_add(b),
+        "-" => a.wrapping_sub(b),
+        "*" => a.wrapping_mul(b),
+        "/" | "%" if b == 0 => return Err("division by zero".to_string()),
+        "/" => a.wrapping_div(b),
+        "%" => a.wrapping_rem(b),
+        "<" => (a < b) as i64,
+        "<=" => (a <= b) as i64,
+        ">" => (a > b) as i64,
+        ">=" => (a >= b) as i64,
+        "==" => (a == b) as i64,
+        "!=" => (a != b) as i64,
+        "&" => a & b,
+        "|" => a | b,
+        "^" => a ^ b,
+        _ => return Err(format!("unknown operator `{op}`")),
+    })
+}
+
+fn eval(expr: &Expr, ctx: &mut Context) -> Result<i64, String> {
+    match expr {
+        Expr::Number(n) => Ok(*n),
+        Expr::Variable(name) => Ok(variable(ctx, name)),
+        Expr::Unary(op, e) => {
+            let v = eval(e, ctx)?;
+            Ok(match op.as_str() {
+                "!" => (v == 0) as i64,
+                "-" => v.wrapping_neg(),
+                "~" => !v,
+                _ => v,
+            })
+        }
+        Expr::Binary(op, a, b) => match op.as_str() {
+            "&&" => Ok((eval(a, ctx)? != 0 && eval(b, ctx)? != 0) as i64),
+            "||" => Ok((eval(a, ctx)? != 0 || eval(b, ctx)? != 0) as i64),
+            _ => {
+                let a = eval(a, ctx)?;
+                let b = eval(b, ctx)?;
+                apply(op, a, b)
+            }
+        },
+        Expr::Conditional(condition, then, otherwise) => {
+            if eval(condition, ctx)? != 0 {
+                eval(then, ctx)
+            } else {
+                eval(otherwise, ctx)
+            }
+        }
+        Expr::Assign(name, op, e) => {
+            let v = eval(e, ctx)?;
+            let v = if op.is_empty() {
+                v
+            } else {
+                apply(op, variable(ctx, name), v)?
+            };
+            ctx.set_variable(name, &v.to_string());
+            Ok(v)
+        }
+        Expr::Increment(name, step, prefix) => {
+            let old = variable(ctx, name);
+            let new = old.wrapping_add(*step);
+            ctx.set_variable(name, &new.to_string());
+            Ok(if *prefix { new } else { old })
+        }
+    }
+}
+
+/// Evaluates an arithmetic expression; an empty expression is 0.
+pub fn evaluate(str: &str, ctx: &mut Context) -> Result<i64, String> {
+    let tokens = tokenize(str)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = ArithmeticParser {
+        tokens,
+        position: 0,
+    };
+    let expr = parser.parse_assignment()?;
+    if let Some(t) = parser.peek() {
+        return Err(format!("unexpected {t:?} after expression"));
+    }
+    eval(&expr, ctx)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreting::interpreter::Context;
+
+    use super::evaluate;
+
+    #[test]
+    fn test_precedence() {
+        let mut ctx = Context::new();
+        assert_eq!(evaluate("1 + 2 * 3", &mut ctx), Ok(7));
+        assert_eq!(evaluate("(1 + 2) * 3", &mut ctx), Ok(9));
+        assert_eq!(evaluate("10 - 4 - 3", &mut ctx), Ok(3));
+        assert_eq!(evaluate("1 < 2 && 3 >= 4 || !0", &mut ctx), Ok(1));
+        assert_eq!(evaluate("-2 * 3 % 4", &mut ctx), Ok(-2));
+        assert_eq!(evaluate("1 ? 5 : 6", &mut ctx), Ok(5));
+        assert_eq!(evaluate("", &mut ctx), Ok(0));
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut ctx = Context::new();
+        assert_eq!(evaluate("i=2", &mut ctx), Ok(2));
+        assert_eq!(evaluate("i++", &mut ctx), Ok(2));
+        assert_eq!(evaluate("++i", &mut ctx), Ok(4));
+        assert_eq!(evaluate("i *= 3", &mut ctx), Ok(12));
+        assert_eq!(evaluate("$i + unset", &mut ctx), Ok(12));
+        assert_eq!(ctx.get_variable("i"), Some("12".to_string()));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut ctx = Context::new();
+        assert!(evaluate("1 / 0", &mut ctx).is_err());
+        assert!(evaluate("1 +", &mut ctx).is_err());
+        assert!(evaluate("(1", &mut ctx).is_err());
+        assert!(evaluate("1 2", &mut ctx).is_err());
+        assert!(evaluate("1 # 2", &mut ctx).is_err());
+    }
+}
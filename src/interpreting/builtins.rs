@@ -0,0 +1,695 @@
+use std::path::PathBuf;
+
+use crate::{completion::spec::CompletionSpec, config::Config};
+
+use super::{
+    conditional,
+    interpreter::{is_a_valid_name, quote_word, Context, LoopControl},
+    script::run_source,
+};
+
+pub type Builtin = fn(&mut Context, &[String]) -> i32;
+
+/// The names `get_builtin` knows, for completion.
+pub const BUILTINS: [&str; 16] = [
+    "cd", "exit", "break", "continue", "local", "export", "source", ".", "return", "test", "[",
+    "config", "history", "complete", "alias", "unalias",
+];
+
+pub fn get_builtin(name: &str) -> Option<Builtin> {
+    match name {
+        "cd" => Some(cd),
+        "exit" => Some(exit),
+        "break" => Some(break_loop),
+        "continue" => Some(continue_loop),
+        "local" => Some(local),
+        "export" => Some(export),
+        "source" | "." => Some(source),
+        "return" => Some(return_function),
+        "test" => Some(test),
+        "[" => Some(bracket),
+        "config" => Some(config),
+        "history" => Some(history),
+        "complete" => Some(complete),
+        "alias" => Some(alias),
+        "unalias" => Some(unalias),
+        _ => None,
+    }
+}
+
+fn cd(ctx: &mut Context, args: &[String]) -> i32 {
+    let target = match args.first() {
+        Some(t) => t.clone(),
+        None => match ctx.get_variable("HOME") {
+            Some(h) => h,
+            None => {
+                eprintln!("tinysh: cd: HOME not set");
+                return 1;
+            }
+        },
+    };
+    match std::env::set_current_dir(&target) {
+        Ok(()) => {
+            if let Ok(pwd) = std::env::current_dir() {
+                std::env::set_var("PWD", pwd);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("tinysh: cd: {target}: {e}");
+            1
+        }
+    }
+}
+
+fn exit(ctx: &mut Context, args: &[String]) -> i32 {
+    let code = match args.first() {
+        None => ctx.last_status,
+        Some(s) => match s.parse() {
+            Ok(c) => c,
+            Err(_) => {
+                eprintln!("tinysh: exit: {s}: numeric argument required");
+                2
+            }
+        },
+    };
+    ctx.exit = Some(code);
+    code
+}
+
+/// Shared by `break` and `continue`: `n` defaults to 1 and is capped at the
+/// number of enclosing loops.
+fn loop_control(
+    ctx: &mut Context,
+    name: &str,
+    args: &[String],
+    control: fn(u32) -> LoopControl,
+) -> i32 {
+    let n = match args.first() {
+        None => 1,
+        Some(s) => match s.parse::<u32>() {
+            Ok(n) if n > 0 => n,
+            _ => {
+                eprintln!("tinysh: {name}: {s}: loop count out of range");
+                return 1;
+            }
+        },
+    };
+    if ctx.loop_depth == 0 {
+        eprintln!("tinysh: {name}: only meaningful in a loop");
+        return 0;
+    }
+    ctx.loop_control = Some(control(n.min(ctx.loop_depth)));
+    0
+}
+
+fn break_loop(ctx: &mut Context, args: &[String]) -> i32 {
+    loop_control(ctx, "break", args, LoopControl::Break)
+}
+
+fn continue_loop(ctx: &mut Context, args: &[String]) -> i32 {
+    loop_control(ctx, "continue", args, LoopControl::Continue)
+}
+
+/// `local name[=value]...`: the previous values come back when the function
+/// returns.
+fn local(ctx: &mut Context, args: &[String]) -> i32 {
+    let Some(frame) = ctx.locals.last_mut() else {
+        eprintln!("tinysh: local: can only be used in a function");
+        return 1;
+    };
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+        if !is_a_valid_name(name) {
+            eprintln!("tinysh: local: `{arg}': not a valid identifier");
+            status = 1;
+            continue;
+        }
+        if !frame.contains_key(name) {
+            frame.insert(name.to_string(), ctx.variables.get(name).cloned());
+        }
+        ctx.variables.insert(name.to_string(), value.to_string());
+    }
+    status
+}
+
+/// `export name[=value]...` moves variables to the environment of the
+/// commands the shell starts; without arguments it lists that environment.
+fn export(ctx: &mut Context, args: &[String]) -> i32 {
+    if args.is_empty() {
+        let mut variables: Vec<(String, String)> = std::env::vars().collect();
+        variables.sort();
+        for (name, value) in variables {
+            println!("export {name}={value}");
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), ctx.variables.get(arg).cloned()),
+        };
+        if !is_a_valid_name(name) {
+            eprintln!("tinysh: export: `{arg}': not a valid identifier");
+            status = 1;
+            continue;
+        }
+        ctx.variables.remove(name);
+        std::env::set_var(name, value.unwrap_or_default());
+    }
+    status
+}
+
+/// `alias name=value...` defines aliases for the running shell, as a
+/// startup file does; `alias name` shows one and `alias` lists them. Only
+/// `config set aliases.name` saves them.
+fn alias(ctx: &mut Context, args: &[String]) -> i32 {
+    if args.is_empty() {
+        for (name, value) in ctx.all_aliases() {
+            println!("alias {name}={}", quote_word(value));
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for arg in args {
+        match arg.split_once('=') {
+            Some((name, _)) if name.is_empty() || name.contains(['/', '$', '"', ' ']) => {
+                eprintln!("tinysh: alias: `{name}': invalid alias name");
+                status = 1;
+            }
+            Some((name, value)) => {
+                ctx.aliases
+                    .insert(name.to_string(), Some(value.to_string()));
+            }
+            None => match ctx.alias(arg) {
+                Some(value) => println!("alias {arg}={}", quote_word(value)),
+                None => {
+                    eprintln!("tinysh: alias: {arg}: not found");
+                    status = 1;
+                }
+            },
+        }
+    }
+    status
+}
+
+/// `unalias name...` removes aliases, `unalias -a` all of them, for the
+/// running shell only.
+fn unalias(ctx: &mut Context, args: &[String]) -> i32 {
+    if args.is_empty() {
+        eprintln!("tinysh: unalias: usage: unalias [-a] name [name ...]");
+        return 2;
+    }
+    let names: Vec<String> = if args.iter().any(|arg| arg == "-a") {
+        ctx.all_aliases().into_keys().map(String::from).collect()
+    } else {
+        args.to_vec()
+    };
+    let mut status = 0;
+    for name in names {
+        if ctx.alias(&name).is_none() {
+            eprintln!("tinysh: unalias: {name}: not found");
+            status = 1;
+            continue;
+        }
+        ctx.aliases.insert(name, None);
+    }
+    status
+}
+
+/// A name without `/` is looked up in `$PATH`, then in the current directory.
+fn find_sourced_file(ctx: &Context, name: &str) -> Option<PathBuf> {
+    if !name.contains('/') {
+        let path = ctx.get_variable("PATH").unwrap_or_default();
+        for dir in std::env::split_paths(&path) {
+            let candidate = dir.join(name);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+    }
+    let path = PathBuf::from(name);
+    path.is_file().then_some(path)
+}
+
+/// `source file [args...]` runs the file in the current shell, with `args`
+/// as positional parameters while it runs.
+fn source(ctx: &mut Context, args: &[String]) -> i32 {
+    let Some((name, args)) = args.split_first() else {
+        eprintln!("tinysh: source: filename argument required");
+        return 2;
+    };
+    let Some(path) = find_sourced_file(ctx, name) else {
+        eprintln!("tinysh: source: {name}: file not found");
+        return 1;
+    };
+    let content = match std::fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("tinysh: source: {name}: {e}");
+            return 1;
+        }
+    };
+    let arguments = if args.is_empty() {
+        None
+    } else {
+        Some(std::mem::replace(&mut ctx.arguments, args.to_vec()))
+    };
+    ctx.source_depth += 1;
+    let status = run_source(&content, ctx);
+    let status = ctx.returning.take().unwrap_or(status);
+    ctx.source_depth -= 1;
+    if let Some(arguments) = arguments {
+        ctx.arguments = arguments;
+    }
+    status
+}
+
+fn return_function(ctx: &mut Context, args: &[String]) -> i32 {
+    if ctx.locals.is_empty() && ctx.source_depth == 0 {
+        eprintln!("tinysh: return: can only be used in a function or a sourced file");
+        return 1;
+    }
+    let code = match args.first() {
+        None => ctx.last_status,
+        Some(s) => match s.parse() {
+            Ok(c) => c,
+            Err(_) => {
+                eprintln!("tinysh: return: {s}: numeric argument required");
+                2
+            }
+        },
+    };
+    ctx.returning = Some(code);
+    code
+}
+
+fn test(_ctx: &mut Context, args: &[String]) -> i32 {
+    match conditional::test(args) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("tinysh: test: {e}");
+            2
+        }
+    }
+}
+
+/// `[ ... ]` is `test` with a mandatory closing bracket.
+fn bracket(ctx: &mut Context, args: &[String]) -> i32 {
+    match args.split_last() {
+        Some((last, args)) if last == "]" => test(ctx, args),
+        _ => {
+            eprintln!("tinysh: [: missing `]'");
+            2
+        }
+    }
+}
+
+/// `config [show]`, `config get key`, `config set key value...` which also
+/// saves the file, and `config reload` to pick up changes made to it.
+/// Outside of the prompt, `set` only changes the running shell.
+fn config(ctx: &mut Context, args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    match args.as_slice() {
+        [] | ["show"] => {
+            if let Some(file) = &ctx.config_file {
+                println!("# {}", file.display());
+            }
+            for (key, value) in ctx.config.entries() {
+                println!("{key} = {value}");
+            }
+            0
+        }
+        ["get", key] => match ctx.config.get(key) {
+            Some(value) => {
+                println!("{value}");
+                0
+            }
+            None => {
+                eprintln!("tinysh: config: {key}: not set");
+                1
+            }
+        },
+        ["set", key, value @ ..] => {
+            if let Err(e) = ctx.config.set(key, &value.join(" ")) {
+                eprintln!("tinysh: config: {e}");
+                return 1;
+            }
+            ctx.config.apply_path();
+            if let Some(name) = key.strip_prefix("aliases.") {
+                ctx.aliases.remove(name);
+            }
+            let Some(file) = &ctx.config_file else {
+                return 0;
+            };
+            match ctx.config.store(file) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("tinysh: config: could not save: {e}");
+                    1
+                }
+            }
+        }
+        ["reload"] => match ctx.config_file.as_deref().map(Config::load) {
+            None => {
+                eprintln!("tinysh: config: no configuration file");
+                1
+            }
+            Some(Ok(config)) => {
+                config.apply_path();
+                ctx.config = config;
+                0
+            }
+            Some(Err(e)) => {
+                eprintln!("tinysh: config: {e}");
+                1
+            }
+        },
+        _ => {
+            eprintln!("tinysh: config: usage: config [show | get key | set key value | reload]");
+            2
+        }
+    }
+}
+
+/// `history` lists the entries with their number for `!n`, `history n`
+/// the last `n` and `history text` those containing `text`.
+fn history(ctx: &mut Context, args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    match args.as_slice() {
+        ["-c"] => {
+            ctx.history.clear();
+            0
+        }
+        ["-d", position] => {
+            let count = ctx.history.entries.len();
+            // negative positions count from the end
+            let index = match position.parse::<i64>() {
+                Ok(n) if n < 0 => count.checked_sub(n.unsigned_abs() as usize),
+                Ok(n) => (n as usize).checked_sub(1),
+                Err(_) => None,
+            };
+            if index.is_some_and(|i| ctx.history.delete(i)) {
+                0
+            } else {
+                eprintln!("tinysh: history: {position}: position out of range");
+                1
+            }
+        }
+        [] => list_history(ctx, |_, _| true),
+        [filter] if !filter.starts_with('-') => match filter.parse::<usize>() {
+            Ok(n) => {
+                let first = ctx.history.entries.len().saturating_sub(n);
+                list_history(ctx, |i, _| i >= first)
+            }
+            Err(_) => list_history(ctx, |_, entry| entry.contains(filter)),
+        },
+        _ => {
+            eprintln!("tinysh: history: usage: history [n | text | -c | -d n]");
+            2
+        }
+    }
+}
+
+fn list_history(ctx: &Context, keep: impl Fn(usize, &str) -> bool) -> i32 {
+    for (i, entry) in ctx.history.entries.iter().enumerate() {
+        if keep(i, entry) {
+            println!("{:5}  {entry}", i + 1);
+        }
+    }
+    0
+}
+
+/// `complete [-W words] [-S subcommands] [-O flags] [-A action] [-F function]
+/// [-X extensions] name...` declares the completions of the arguments of
+/// commands, `complete -r [name...]` removes them and `complete [-p name...]`
+/// prints them as commands.
+fn complete(ctx: &mut Context, args: &[String]) -> i32 {
+    match args.first().map(|a| a.as_str()) {
+        None | Some("-p") => {
+            let mut names: Vec<&String> = match args.get(1..).unwrap_or_default() {
+                [] => ctx.completion_specs.keys().collect(),
+                names => names.iter().collect(),
+            };
+            names.sort();
+            let mut status = 0;
+            for name in names {
+                match ctx.completion_specs.get(name) {
+                    Some(spec) => println!("{}", spec.to_command(name)),
+                    None => {
+                        eprintln!("tinysh: complete: {name}: no completion specification");
+                        status = 1;
+                    }
+                }
+            }
+            status
+        }
+        Some("-r") => {
+            match &args[1..] {
+                [] => ctx.completion_specs.clear(),
+                names => names.iter().for_each(|name| {
+                    ctx.completion_specs.remove(name);
+                }),
+            }
+            0
+        }
+        Some(_) => match CompletionSpec::parse(args) {
+            Ok((_, [])) => {
+                eprintln!("tinysh: complete: usage: complete [-W words] [-S subcommands] [-O flags] [-A action] [-F function] [-X extensions] name...");
+                2
+            }
+            Ok((spec, names)) => {
+                for name in names {
+                    ctx.completion_specs.insert(name.clone(), spec.clone());
+                }
+                0
+            }
+            Err(e) => {
+                eprintln!("tinysh: complete: {e}");
+                2
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreting::interpreter::{Context, LoopControl};
+
+    use super::{get_builtin, BUILTINS};
+
+    #[test]
+    fn test_names() {
+        assert!(BUILTINS.iter().all(|name| get_builtin(name).is_some()));
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut ctx = Context::new();
+        ctx.last_status = 3;
+        let exit = get_builtin("exit").unwrap();
+        assert_eq!(exit(&mut ctx, &[]), 3);
+        assert_eq!(ctx.exit, Some(3));
+        assert_eq!(exit(&mut ctx, &["7".to_string()]), 7);
+        assert_eq!(exit(&mut ctx, &["x".to_string()]), 2);
+    }
+
+    #[test]
+    fn test_cd_missing_directory() {
+        let mut ctx = Context::new();
+        let cd = get_builtin("cd").unwrap();
+        assert_eq!(cd(&mut ctx, &["/nonexistent/tinysh".to_string()]), 1);
+        assert!(get_builtin("nope").is_none());
+    }
+
+    #[test]
+    fn test_break() {
+        let mut ctx = Context::new();
+        let brk = get_builtin("break").unwrap();
+        assert_eq!(brk(&mut ctx, &[]), 0);
+        assert_eq!(ctx.loop_control, None);
+        ctx.loop_depth = 2;
+        assert_eq!(brk(&mut ctx, &["0".to_string()]), 1);
+        assert_eq!(brk(&mut ctx, &["5".to_string()]), 0);
+        assert_eq!(ctx.loop_control, Some(LoopControl::Break(2)));
+    }
+
+    #[test]
+    fn test_local_outside_function() {
+        let mut ctx = Context::new();
+        let local = get_builtin("local").unwrap();
+        assert_eq!(local(&mut ctx, &["a=1".to_string()]), 1);
+        assert_eq!(ctx.get_variable("a"), None);
+        let ret = get_builtin("return").unwrap();
+        assert_eq!(ret(&mut ctx, &[]), 1);
+        assert_eq!(ctx.returning, None);
+    }
+
+    #[test]
+    fn test_export() {
+        let mut ctx = Context::new();
+        let name = format!("TINYSH_EXPORT_{}", std::process::id());
+        ctx.variables.insert(name.clone(), "1".to_string());
+        let export = get_builtin("export").unwrap();
+        assert_eq!(export(&mut ctx, &[name.clone(), "1a=b".to_string()]), 1);
+        assert_eq!(std::env::var(&name), Ok("1".to_string()));
+        assert!(!ctx.variables.contains_key(&name));
+        ctx.set_variable(&name, "2");
+        assert_eq!(std::env::var(&name), Ok("2".to_string()));
+        std::env::remove_var(name);
+    }
+
+    #[test]
+    fn test_source() {
+        let dir = std::env::temp_dir().join(format!("tinysh-source-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("lib.sh"),
+            "f() { s=$1; }\nn=$#\nreturn 3\nn=unreachable\n",
+        )
+        .unwrap();
+        let mut ctx = Context::new();
+        ctx.variables
+            .insert("PATH".to_string(), dir.to_str().unwrap().to_string());
+        ctx.arguments = vec!["outer".to_string()];
+        let source = get_builtin(".").unwrap();
+        assert_eq!(
+            source(
+                &mut ctx,
+                &["lib.sh".to_string(), "a".to_string(), "b".to_string()]
+            ),
+            3
+        );
+        assert_eq!(ctx.get_variable("n"), Some("2".to_string()));
+        assert_eq!(ctx.arguments, vec!["outer".to_string()]);
+        assert!(ctx.functions.contains_key("f"));
+        assert_eq!(ctx.returning, None);
+        assert_eq!(ctx.source_depth, 0);
+        assert_eq!(source(&mut ctx, &["missing.sh".to_string()]), 1);
+        assert_eq!(source(&mut ctx, &[]), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_config() {
+        let mut ctx = Context::new();
+        let config = get_builtin("config").unwrap();
+        assert_eq!(
+            config(&mut ctx, &["get".to_string(), "verbose".to_string()]),
+            0
+        );
+        assert_eq!(
+            config(&mut ctx, &["get".to_string(), "nope".to_string()]),
+            1
+        );
+        assert_eq!(
+            config(
+                &mut ctx,
+                &[
+                    "set".to_string(),
+                    "history_size".to_string(),
+                    "x".to_string()
+                ]
+            ),
+            1
+        );
+        assert_eq!(ctx.config.history_size, 1000);
+        assert_eq!(config(&mut ctx, &["frobnicate".to_string()]), 2);
+        let set = |key: &str, value: &str| ["set", key, value].map(|a| a.to_string());
+        // without a file, as in scripts, nothing is saved
+        assert_eq!(config(&mut ctx, &set("history_size", "20")), 0);
+        assert_eq!(ctx.config.history_size, 20);
+        assert_eq!(config(&mut ctx, &["reload".to_string()]), 1);
+
+        let dir = std::env::temp_dir().join(format!("tinysh-config-{}", std::process::id()));
+        let file = dir.join("config.toml");
+        ctx.config_file = Some(file.clone());
+        assert_eq!(config(&mut ctx, &set("prompt", "$ ")), 0);
+        assert!(std::fs::read_to_string(&file)
+            .unwrap()
+            .contains("history_size = 20"));
+        ctx.config = Default::default();
+        assert_eq!(config(&mut ctx, &["reload".to_string()]), 0);
+        assert_eq!(ctx.config.prompt, "$ ");
+        assert_eq!(ctx.config.history_size, 20);
+
+        // aliases of the session are not saved with the rest
+        let alias = get_builtin("alias").unwrap();
+        assert_eq!(alias(&mut ctx, &["x=y".to_string()]), 0);
+        assert_eq!(config(&mut ctx, &set("prompt", "% ")), 0);
+        assert!(!std::fs::read_to_string(&file).unwrap().contains("x ="));
+        assert_eq!(config(&mut ctx, &set("aliases.s", "ls")), 0);
+        assert_eq!(config(&mut ctx, &["reload".to_string()]), 0);
+        assert_eq!(ctx.alias("x"), Some("y"));
+        assert_eq!(ctx.alias("s"), Some("ls"));
+        assert_eq!(config(&mut ctx, &set("aliases.x", "z")), 0);
+        assert_eq!(ctx.alias("x"), Some("z"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_alias() {
+        let mut ctx = Context::new();
+        ctx.config
+            .aliases
+            .insert("g".to_string(), "git".to_string());
+        let alias = get_builtin("alias").unwrap();
+        let unalias = get_builtin("unalias").unwrap();
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(alias(&mut ctx, &words(&["ll=ls -l"])), 0);
+        assert_eq!(ctx.alias("ll"), Some("ls -l"));
+        assert_eq!(alias(&mut ctx, &words(&["ll", "g"])), 0);
+        assert_eq!(alias(&mut ctx, &words(&["nope"])), 1);
+        assert_eq!(alias(&mut ctx, &words(&["=x", "a/b=c"])), 1);
+        assert_eq!(ctx.all_aliases().len(), 2);
+        assert_eq!(alias(&mut ctx, &[]), 0);
+        assert_eq!(unalias(&mut ctx, &words(&["g", "nope"])), 1);
+        assert_eq!(ctx.alias("g"), None);
+        assert_eq!(ctx.all_aliases().len(), 1);
+        assert_eq!(unalias(&mut ctx, &words(&["-a"])), 0);
+        assert!(ctx.all_aliases().is_empty());
+        assert_eq!(unalias(&mut ctx, &[]), 2);
+        // the configuration keeps its own
+        assert_eq!(ctx.config.aliases.len(), 1);
+        assert_eq!(alias(&mut ctx, &words(&["g=git status"])), 0);
+        assert_eq!(ctx.alias("g"), Some("git status"));
+        assert_eq!(ctx.config.aliases["g"], "git");
+    }
+
+    #[test]
+    fn test_history() {
+        let mut ctx = Context::new();
+        for line in ["ls", "pwd", "date"] {
+            ctx.history.add(line, "", 10);
+        }
+        let history = get_builtin("history").unwrap();
+        assert_eq!(history(&mut ctx, &["2".to_string()]), 0);
+        assert_eq!(history(&mut ctx, &["-d".to_string(), "-1".to_string()]), 0);
+        assert_eq!(history(&mut ctx, &["-d".to_string(), "1".to_string()]), 0);
+        assert_eq!(ctx.history.entries, vec!["pwd"]);
+        assert_eq!(history(&mut ctx, &["-d".to_string(), "5".to_string()]), 1);
+        assert_eq!(history(&mut ctx, &["-x".to_string()]), 2);
+        assert_eq!(history(&mut ctx, &["-c".to_string()]), 0);
+        assert!(ctx.history.entries.is_empty());
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut ctx = Context::new();
+        let complete = get_builtin("complete").unwrap();
+        let args = |args: &[&str]| -> Vec<String> { args.iter().map(|a| a.to_string()).collect() };
+        assert_eq!(complete(&mut ctx, &args(&["-W", "a b", "x", "y"])), 0);
+        assert_eq!(ctx.completion_specs.len(), 2);
+        assert_eq!(complete(&mut ctx, &args(&["-p", "x"])), 0);
+        assert_eq!(complete(&mut ctx, &args(&["-p", "z"])), 1);
+        assert_eq!(complete(&mut ctx, &args(&["-r", "x"])), 0);
+        assert_eq!(ctx.completion_specs.len(), 1);
+        assert_eq!(complete(&mut ctx, &args(&["-W", "a"])), 2);
+        assert_eq!(complete(&mut ctx, &args(&["-A", "nope", "x"])), 2);
+        assert_eq!(complete(&mut ctx, &args(&["-r"])), 0);
+        assert!(ctx.completion_specs.is_empty());
+    }
+}
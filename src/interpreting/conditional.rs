@@ -0,0 +1,303 @@
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::parsing::ast::{Ast, Parameters, TestWord};
+
+use super::{
+    expansion::{expand, matches_glob},
+    interpreter::Context,
+};
+
+fn is_unary(op: &str) -> bool {
+    matches!(
+        op,
+        "-e" | "-f" | "-d" | "-r" | "-w" | "-x" | "-s" | "-L" | "-h" | "-z" | "-n"
+    )
+}
+
+fn is_binary(op: &str) -> bool {
+    matches!(
+        op,
+        "=" | "==" | "!=" | "<" | ">" | "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge"
+    )
+}
+
+#[cfg(unix)]
+fn accessible(path: &str, op: &str) -> bool {
+    let mode = match op {
+        "-r" => libc::R_OK,
+        "-w" => libc::W_OK,
+        _ => libc::X_OK,
+    };
+    let Ok(path) = std::ffi::CString::new(path) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), mode) == 0 }
+}
+
+#[cfg(not(unix))]
+fn accessible(path: &str, _op: &str) -> bool {
+    Path::new(path).exists()
+}
+
+fn unary(op: &str, arg: &str) -> bool {
+    let path = Path::new(arg);
+    match op {
+        "-z" => arg.is_empty(),
+        "-n" => !arg.is_empty(),
+        "-e" => path.exists(),
+        "-f" => path.is_file(),
+        "-d" => path.is_dir(),
+        "-s" => path.metadata().is_ok_and(|m| m.len() > 0),
+        "-L" | "-h" => path.is_symlink(),
+        op => accessible(arg, op),
+    }
+}
+
+fn integer(s: &str) -> Result<i64, String> {
+    s.trim()
+        .parse()
+        .map_err(|_| format!("{s}: integer expression expected"))
+}
+
+/// `patterns` makes `==`/`!=` match a glob, as they do inside `[[ ]]`.
+fn binary(left: &str, op: &str, right: &str, patterns: bool) -> Result<bool, String> {
+    Ok(match op {
+        "=" | "==" if patterns => matches_glob(right, left),
+        "!=" if patterns => !matches_glob(right, left),
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "=~" => Regex::new(right)
+            .map_err(|_| format!("{right}: invalid regular expression"))?
+            .is_match(left),
+        "-eq" => integer(left)? == integer(right)?,
+        "-ne" => integer(left)? != integer(right)?,
+        "-lt" => integer(left)? < integer(right)?,
+        "-le" => integer(left)? <= integer(right)?,
+        "-gt" => integer(left)? > integer(right)?,
+        "-ge" => integer(left)? >= integer(right)?,
+        op => return Err(format!("{op}: unknown operator")),
+    })
+}
+
+/// Arguments of `test` and `[`, with `!`, `-a`, `-o` and parentheses
+/// combining the tests from the tightest to the loosest.
+struct TestParser<'a> {
+    args: &'a [String],
+    position: usize,
+}
+
+impl TestParser<'_> {
+    fn peek(&self, offset: usize) -> Option<&str> {
+        self.args.get(self.position + offset).map(|s| s.as_str())
+    }
+
+    fn parse_or(&mut self) -> Result<bool, String> {
+        let mut result = self.parse_and()?;
+        while self.peek(0) == Some("-o") {
+            self.position += 1;
+            let right = self.parse_and()?;
+            result = result || right;
+        }
+        Ok(result)
+    }
+
+    fn parse_and(&mut self) -> Result<bool, String> {
+        let mut result = self.parse_not()?;
+        while self.peek(0) == Some("-a") {
+            self.position += 1;
+            let right = self.parse_not()?;
+            result = result && right;
+        }
+        Ok(result)
+    }
+
+    fn parse_not(&mut self) -> Result<bool, String> {
+        if self.peek(0) == Some("!") && self.peek(1).is_some() {
+            self.position += 1;
+            return Ok(!self.parse_not()?);
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<bool, String> {
+        let Some(first) = self.peek(0) else {
+            return Err("argument expected".to_string());
+        };
+        if let (Some(op), Some(right)) = (self.peek(1), self.peek(2)) {
+            if is_binary(op) {
+                let result = binary(first, op, right, false);
+                self.position += 3;
+                return result;
+            }
+        }
+        if first == "(" && self.peek(1).is_some() {
+            self.position += 1;
+            let result = self.parse_or()?;
+            if self.peek(0) != Some(")") {
+                return Err("missing `)`".to_string());
+            }
+            self.position += 1;
+            return Ok(result);
+        }
+        if is_unary(first) {
+            if let Some(arg) = self.peek(1) {
+                let result = unary(first, arg);
+                self.position += 2;
+                return Ok(result);
+            }
+        }
+        let result = !first.is_empty();
+        self.position += 1;
+        Ok(result)
+    }
+}
+
+/// Evaluates the arguments of `test`; no argument at all is false.
+pub fn test(args: &[String]) -> Result<bool, String> {
+    if args.is_empty() {
+        return Ok(false);
+    }
+    let mut parser = TestParser { args, position: 0 };
+    let result = parser.parse_or()?;
+    match parser.peek(0) {
+        Some(arg) => Err(format!("{arg}: unexpected argument")),
+        None => Ok(result),
+    }
+}
+
+/// One test of a `[[ ]]` expression, given as its expanded words. The
+/// right side of `==`, `!=` and `=~` is a pattern unless it was quoted.
+fn primary(words: &[TestWord]) -> Result<bool, String> {
+    match words {
+        [word] => Ok(!word.text.is_empty()),
+        [op, arg] if is_unary(&op.text) => Ok(unary(&op.text, &arg.text)),
+        [left, op, right] if op.text == "=~" && right.quoted => {
+            binary(&left.text, "=~", &regex::escape(&right.text), false)
+        }
+        [left, op, right] if is_binary(&op.text) || op.text == "=~" => {
+            binary(&left.text, &op.text, &right.text, !right.quoted)
+        }
+        _ => Err(format!(
+            "{}: conditional operator expected",
+            Parameters::TestWords(words.to_vec())
+        )),
+    }
+}
+
+/// Evaluates the expression of a `[[ ]]`: `&&`, `||` and parentheses come
+/// from the shell grammar, `<` and `>` from what would be redirections.
+pub fn evaluate(ast: &Ast, ctx: &Context) -> Result<bool, String> {
+    let Ast::Node {
+        value, left, right, ..
+    } = ast
+    else {
+        return Err(format!("{ast}: unexpected in `[[ ]]`"));
+    };
+    match value {
+        Parameters::And => Ok(evaluate(left, ctx)? && evaluate(right, ctx)?),
+        Parameters::Or => Ok(evaluate(left, ctx)? || evaluate(right, ctx)?),
+        Parameters::Subshell => evaluate(left, ctx),
+        Parameters::Not => Ok(!evaluate(left, ctx)?),
+        Parameters::LeftRedirection | Parameters::RightRedirection if **right != Ast::Nil => {
+            let op = value.to_string();
+            binary(
+                &expand(&left.to_string(), ctx),
+                &op,
+                &expand(&right.to_string(), ctx),
+                true,
+            )
+        }
+        Parameters::TestWords(words) => {
+            let words: Vec<TestWord> = words
+                .iter()
+                .map(|word| TestWord {
+                    text: expand(&word.text, ctx),
+                    quoted: word.quoted,
+                })
+                .collect();
+            primary(&words)
+        }
+        _ => Err(format!("{ast}: unexpected in `[[ ]]`")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        interpreting::interpreter::Context, lexing::lexer::Lexer, parsing::ast::Ast,
+        parsing::parser::init_calc_parser,
+    };
+
+    use super::{evaluate, test};
+
+    fn args(str: &str) -> Vec<String> {
+        str.split(' ').map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_strings_and_integers() {
+        assert_eq!(test(&args("a = a")), Ok(true));
+        assert_eq!(test(&args("a != a")), Ok(false));
+        assert_eq!(test(&args("-z a")), Ok(false));
+        assert_eq!(test(&args("-n")), Ok(true));
+        assert_eq!(test(&[]), Ok(false));
+        assert_eq!(test(&args("10 -gt 9")), Ok(true));
+        assert_eq!(test(&args("a* == abc")), Ok(false));
+        assert!(test(&args("a -lt 1")).is_err());
+        assert!(test(&args("a b")).is_err());
+    }
+
+    #[test]
+    fn test_combinations() {
+        assert_eq!(test(&args("! a = b")), Ok(true));
+        assert_eq!(test(&args("a = b -o 1 -eq 1")), Ok(true));
+        assert_eq!(test(&args("1 -eq 1 -a ( a = b -o -n x )")), Ok(true));
+        assert_eq!(test(&args("! ( -n x )")), Ok(false));
+        assert!(test(&args("( -n x")).is_err());
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = std::env::temp_dir();
+        let dir = dir.to_str().unwrap();
+        assert_eq!(test(&args(&format!("-d {dir} -a -e {dir}"))), Ok(true));
+        assert_eq!(test(&args(&format!("-f {dir}"))), Ok(false));
+        assert_eq!(test(&args("-x /nonexistent/tinysh")), Ok(false));
+    }
+
+    #[test]
+    fn test_double_brackets() {
+        let mut ctx = Context::new();
+        ctx.variables.insert("a".to_string(), "hello".to_string());
+        for (str, expected) in [
+            ("[[ $a == h*o ]]", Ok(true)),
+            ("[[ $a != h* ]]", Ok(false)),
+            ("[[ $a =~ ^h[el]+o$ ]]", Ok(true)),
+            ("[[ -z $a || ( 1 -lt 2 && b > a ) ]]", Ok(true)),
+            ("[[ $a ]]", Ok(true)),
+            ("[[ ! $a == h* || not -n $a ]]", Ok(false)),
+            ("[[ $nope ]]", Ok(false)),
+            ("[[ bc =~ ^(a|b)c ]]", Ok(true)),
+            ("[[ abc =~ ^(a|b)c ]]", Ok(false)),
+            ("[[ $a =~ ^(x|h)(e|a)l+o$ && $a == hello ]]", Ok(true)),
+            ("[[ h.llo =~ \"h.l\" ]]", Ok(true)),
+            ("[[ $a =~ \"h.l\" ]]", Ok(false)),
+            ("[[ $a == \"h*\" ]]", Ok(false)),
+            ("[[ h* == \"h*\" ]]", Ok(true)),
+        ] {
+            let lexed = Lexer {
+                str: str.to_string(),
+            }
+            .lex_spanned();
+            let ast = init_calc_parser(str, &lexed).parse().unwrap();
+            let Ast::Test { expression, .. } = ast else {
+                panic!("{str} is not a test");
+            };
+            assert_eq!(evaluate(&expression, &ctx), expected, "{str}");
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use super::interpreter::Context;
+
+/// Expands a leading `~` to `$HOME`, then `$NAME`, `${NAME}`, `$?` and the
+/// positional parameters `$1`..`$9`, `${10}`, `$#`, `$@`, `$*` anywhere in
+/// the word. Unknown variables expand to nothing, a `$` not followed by a name
+/// is kept as is. `\$`, `\\`, `\"` and `` \` `` give the character after the
+/// backslash, other backslashes stay.
+pub fn expand(word: &str, ctx: &Context) -> String {
+    let word = expand_tilde(word, ctx);
+    let mut result = String::new();
+    let mut chars = word.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            // as in double quotes, only these lose their meaning
+            match chars.peek() {
+                Some(next @ ('$' | '\\' | '"' | '`')) => {
+                    result.push(*next);
+                    chars.next();
+                }
+                _ => result.push(c),
+            }
+            continue;
+        }
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('?') => {
+                chars.next();
+                result += &ctx.last_status.to_string();
+            }
+            Some('#') => {
+                chars.next();
+                result += &ctx.arguments.len().to_string();
+            }
+            Some('@') | Some('*') => {
+                chars.next();
+                result += &ctx.arguments.join(" ");
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let name = c.to_string();
+                chars.next();
+                result += &lookup(&name, ctx);
+            }
+            Some('{') => {
+                chars.next();
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                result += &lookup(&name, ctx);
+            }
+            Some(c) if c.is_alphabetic() || *c == '_' => {
+                let mut name = String::new();
+                while let Some(c) = chars.peek() {
+                    if !(c.is_alphanumeric() || *c == '_') {
+                        break;
+                    }
+                    name.push(*c);
+                    chars.next();
+                }
+                result += &ctx.get_variable(&name).unwrap_or_default();
+            }
+            _ => result.push('$'),
+        }
+    }
+    result
+}
+
+/// Expands every word of a command; a bare `$@` gives one word per
+/// positional parameter instead of a single joined one.
+pub fn expand_words(words: &[String], ctx: &Context) -> Vec<String> {
+    let mut result = Vec::new();
+    for word in words {
+        if word == "$@" {
+            result.extend(ctx.arguments.iter().cloned());
+        } else {
+            result.push(expand(word, ctx));
+        }
+    }
+    result
+}
+
+/// Value of a variable or, for a number, of a positional parameter.
+fn lookup(name: &str, ctx: &Context) -> String {
+    match name.parse::<usize>() {
+        Ok(0) => ctx
+            .script_name
+            .clone()
+            .unwrap_or_else(|| "tinysh".to_string()),
+        Ok(n) => ctx.arguments.get(n - 1).cloned().unwrap_or_default(),
+        Err(_) => ctx.get_variable(name).unwrap_or_default(),
+    }
+}
+
+fn expand_tilde(word: &str, ctx: &Context) -> String {
+    match word.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            ctx.get_variable("HOME").unwrap_or_default() + rest
+        }
+        _ => word.to_string(),
+    }
+}
+
+/// Shell pattern matching on the whole of `text`: `*` matches any run of
+/// characters, `?` a single one and `[...]` a set such as `[a-z_]` or,
+/// negated, `[!0-9]`.
+pub fn matches_glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob(&pattern, &text)
+}
+
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => (0..=text.len()).any(|i| glob(&pattern[1..], &text[i..])),
+        Some('?') => !text.is_empty() && glob(&pattern[1..], &text[1..]),
+        Some('[') => match (text.first(), bracket(&pattern[1..])) {
+            (Some(c), Some((set, negated, len))) => {
+                set_contains(set, *c) != negated && glob(&pattern[len + 1..], &text[1..])
+            }
+            (None, Some(_)) => false,
+            // an unclosed `[` is an ordinary character
+            (_, None) => text.first() == Some(&'[') && glob(&pattern[1..], &text[1..]),
+        },
+        Some(p) => text.first() == Some(p) && glob(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Splits `a-z]rest` into the set `a-z`, whether it was negated and how
+/// many characters it took including the closing `]`.
+fn bracket(pattern: &[char]) -> Option<(&[char], bool, usize)> {
+    let negated = matches!(pattern.first(), Some('!') | Some('^'));
+    let start = negated as usize;
+    // a `]` right after the opening bracket belongs to the set
+    let end = pattern[start..]
+        .iter()
+        .skip(1)
+        .position(|c| *c == ']')
+        .map(|i| i + start + 1)?;
+    Some((&pattern[start..end], negated, end + 1))
+}
+
+fn set_contains(set: &[char], c: char) -> bool {
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == '-' {
+            if set[i] <= c && c <= set[i + 2] {
+                return true;
+            }
+            i += 3;
+        } else {
+            if set[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreting::interpreter::Context;
+
+    use super::{expand, expand_words, matches_glob};
+
+    #[test]
+    fn test_expand_variables() {
+        let mut ctx = Context::new();
+        ctx.variables.insert("a".to_string(), "1".to_string());
+        ctx.last_status = 2;
+        assert_eq!(expand("x$a-$?", &ctx), "x1-2");
+        assert_eq!(expand("${a}b", &ctx), "1b");
+        assert_eq!(expand("$nope", &ctx), "");
+        assert_eq!(expand("5$", &ctx), "5$");
+        assert_eq!(expand("\\$a\\\\$a\\u\\\"", &ctx), "$a\\1\\u\"");
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let mut ctx = Context::new();
+        ctx.variables
+            .insert("HOME".to_string(), "/home/t".to_string());
+        assert_eq!(expand("~", &ctx), "/home/t");
+        assert_eq!(expand("~/src", &ctx), "/home/t/src");
+        assert_eq!(expand("a~", &ctx), "a~");
+        assert_eq!(expand("~user", &ctx), "~user");
+    }
+
+    #[test]
+    fn test_expand_arguments() {
+        let mut ctx = Context::new();
+        ctx.arguments = (1..=10).map(|i| format!("a{i}")).collect();
+        assert_eq!(expand("$1-$10-${10}-$#", &ctx), "a1-a10-a10-10");
+        ctx.arguments.truncate(2);
+        assert_eq!(expand("[$*]", &ctx), "[a1 a2]");
+        assert_eq!(
+            expand_words(&["x".to_string(), "$@".to_string()], &ctx),
+            vec!["x", "a1", "a2"]
+        );
+    }
+
+    #[test]
+    fn test_matches_glob() {
+        assert!(matches_glob("*", ""));
+        assert!(matches_glob("*.rs", "main.rs"));
+        assert!(!matches_glob("*.rs", "main.rsx"));
+        assert!(matches_glob("a?c", "abc"));
+        assert!(!matches_glob("a?c", "ac"));
+        assert!(matches_glob("[a-c]x", "bx"));
+        assert!(!matches_glob("[!a-c]x", "bx"));
+        assert!(matches_glob("[]]", "]"));
+        assert!(matches_glob("[a", "[a"));
+        assert!(matches_glob("-[0-9]*", "-42"));
+    }
+}
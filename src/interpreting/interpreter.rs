@@ -0,0 +1,670 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    rc::Rc,
+    time::Duration,
+};
+
+use crate::{
+    completion::spec::CompletionSpec,
+    config::Config,
+    history::History,
+    lexing::token::CaseTerminator,
+    parsing::ast::{Ast, Parameters},
+};
+
+use super::{
+    arithmetic::evaluate,
+    builtins::get_builtin,
+    conditional,
+    expansion::{expand, expand_words, matches_glob},
+    process::{pipeline, redirect, spawn, subshell, Redirection},
+    script::run_source,
+};
+
+/// Everything a command can change in the shell it runs in.
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    pub variables: HashMap<String, String>,
+    pub last_status: i32,
+    /// Set by `exit`; stops the current list and the read loop.
+    pub exit: Option<i32>,
+    /// Number of loops currently running, so `break 5` stops at the outermost.
+    pub loop_depth: u32,
+    /// Set by `break`/`continue`; unwinds until the targeted loop.
+    pub loop_control: Option<LoopControl>,
+    /// Functions defined with `name() { ... }` or `function name { ... }`.
+    pub functions: HashMap<String, Rc<Ast>>,
+    /// Positional parameters `$1`, `$2`... of the running function.
+    pub arguments: Vec<String>,
+    /// One frame per running function: the values its `local` variables
+    /// shadowed, put back when it returns.
+    pub locals: Vec<HashMap<String, Option<String>>>,
+    /// Set by `return`; unwinds until the function call or `source`.
+    pub returning: Option<i32>,
+    /// Number of files being run by `source`, where `return` is allowed.
+    pub source_depth: u32,
+    /// `$0` when running a script.
+    pub script_name: Option<String>,
+    /// How long the last command line took, for `\D` in the prompt.
+    pub last_duration: Duration,
+    /// Settings from the configuration file, see the `config` builtin.
+    pub config: Config,
+    /// The file `config` saves to and reloads from; only the prompt reads
+    /// one, so that `-c` and scripts leave it alone.
+    pub config_file: Option<PathBuf>,
+    /// Aliases of `alias` and `unalias` for this session only, over those
+    /// of the configuration; `None` hides one of these.
+    pub aliases: BTreeMap<String, Option<String>>,
+    /// Aliases being expanded, which are not expanded again inside their
+    /// own value so that `alias ls='ls -F'` works.
+    pub expanding_aliases: Vec<String>,
+    /// Lines entered at the prompt, saved to the history file.
+    pub history: History,
+    /// Completions declared with `complete`, by command name.
+    pub completion_specs: HashMap<String, CompletionSpec>,
+}
+
+/// How many enclosing loops `break n` or `continue n` still has to leave.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopControl {
+    Break(u32),
+    Continue(u32),
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    /// Shell variables shadow the environment.
+    pub fn get_variable(&self, name: &str) -> Option<String> {
+        match self.variables.get(name) {
+            Some(v) => Some(v.clone()),
+            None => std::env::var(name).ok(),
+        }
+    }
+
+    /// The value of an alias, from `alias` or the configuration.
+    pub fn alias(&self, name: &str) -> Option<&str> {
+        match self.aliases.get(name) {
+            Some(value) => value.as_deref(),
+            None => self.config.aliases.get(name).map(|value| value.as_str()),
+        }
+    }
+
+    /// Every alias with its value, by name.
+    pub fn all_aliases(&self) -> BTreeMap<&str, &str> {
+        let mut aliases: BTreeMap<&str, &str> = self
+            .config
+            .aliases
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        for (name, value) in &self.aliases {
+            match value {
+                Some(value) => aliases.insert(name, value),
+                None => aliases.remove(name.as_str()),
+            };
+        }
+        aliases
+    }
+
+    /// Assigns a variable; one that is already in the environment, such as
+    /// `PATH`, stays exported so that commands see the new value, unless a
+    /// running function made it `local`.
+    pub fn set_variable(&mut self, name: &str, value: &str) {
+        let local = self.locals.iter().any(|frame| frame.contains_key(name));
+        if !local && std::env::var_os(name).is_some() {
+            self.variables.remove(name);
+            std::env::set_var(name, value);
+        } else {
+            self.variables.insert(name.to_string(), value.to_string());
+        }
+    }
+}
+
+pub fn is_a_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => chars.all(|c| c.is_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+pub fn interpret(ast: &Ast, ctx: &mut Context) -> i32 {
+    if let Some(code) = ctx.exit {
+        return code;
+    }
+    if ctx.loop_control.is_some() || ctx.returning.is_some() {
+        return ctx.last_status;
+    }
+    let status = match ast {
+        Ast::Nil => 0,
+        Ast::Node {
+            value, left, right, ..
+        } => match value {
+            Parameters::Semicolon => {
+                let status = interpret(left, ctx);
+                if **right == Ast::Nil {
+                    status
+                } else {
+                    interpret(right, ctx)
+                }
+            }
+            Parameters::And => {
+                let status = interpret(left, ctx);
+                if status == 0 {
+                    interpret(right, ctx)
+                } else {
+                    status
+                }
+            }
+            Parameters::Or => {
+                let status = interpret(left, ctx);
+                if status != 0 {
+                    interpret(right, ctx)
+                } else {
+                    status
+                }
+            }
+            Parameters::Pipe => pipeline(ctx, left, right),
+            Parameters::LeftRedirection | Parameters::RightRedirection => {
+                let direction = if *value == Parameters::LeftRedirection {
+                    Redirection::Output
+                } else {
+                    Redirection::Input
+                };
+                // a bare `> file` has its target on the left
+                let (command, target) = if **right == Ast::Nil {
+                    (&Ast::Nil, left)
+                } else {
+                    (&**left, right)
+                };
+                let path = expand(&target.to_string(), ctx);
+                redirect(ctx, direction, &path, |ctx| interpret(command, ctx))
+            }
+            Parameters::Subshell => subshell(ctx, left),
+            Parameters::Group => interpret(left, ctx),
+            Parameters::Not => (interpret(left, ctx) == 0) as i32,
+            Parameters::Bool(b) => {
+                if *b {
+                    0
+                } else {
+                    1
+                }
+            }
+            Parameters::Call(name, args) => {
+                let mut words = vec![name.clone()];
+                words.extend(args.iter().cloned());
+                run_command(ctx, &words)
+            }
+            p => run_command(ctx, &[p.to_string()]),
+        },
+        Ast::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            if interpret(condition, ctx) == 0 {
+                interpret(then_branch, ctx)
+            } else {
+                interpret(else_branch, ctx)
+            }
+        }
+        Ast::While {
+            condition,
+            body,
+            until,
+            ..
+        } => run_loop(ctx, |ctx| {
+            let mut status = 0;
+            loop {
+                let success = interpret(condition, ctx) == 0;
+                if end_of_iteration(ctx) || success == *until {
+                    break;
+                }
+                status = interpret(body, ctx);
+                if end_of_iteration(ctx) {
+                    break;
+                }
+            }
+            status
+        }),
+        Ast::For {
+            variable,
+            words,
+            body,
+            ..
+        } => {
+            let values = match words {
+                Some(words) => expand_words(words, ctx),
+                None => ctx.arguments.clone(),
+            };
+            run_loop(ctx, |ctx| {
+                let mut status = 0;
+                for value in values {
+                    ctx.set_variable(variable, &value);
+                    status = interpret(body, ctx);
+                    if end_of_iteration(ctx) {
+                        break;
+                    }
+                }
+                status
+            })
+        }
+        Ast::ArithmeticFor {
+            init,
+            condition,
+            update,
+            body,
+            ..
+        } => run_loop(ctx, |ctx| {
+            let mut status = 0;
+            if let Err(e) = evaluate(init, ctx) {
+                eprintln!("tinysh: arithmetic: {e}");
+                return 1;
+            }
+            loop {
+                match evaluate(condition, ctx) {
+                    Ok(0) if !condition.is_empty() => break,
+                    Ok(_) => (),
+                    Err(e) => {
+                        eprintln!("tinysh: arithmetic: {e}");
+                        return 1;
+                    }
+                }
+                status = interpret(body, ctx);
+                if end_of_iteration(ctx) {
+                    break;
+                }
+                if let Err(e) = evaluate(update, ctx) {
+                    eprintln!("tinysh: arithmetic: {e}");
+                    return 1;
+                }
+            }
+            status
+        }),
+        Ast::Case { word, arms, .. } => {
+            let word = expand(word, ctx);
+            let mut status = 0;
+            let mut fall_through = false;
+            for arm in arms {
+                let matched = fall_through
+                    || arm
+                        .patterns
+                        .iter()
+                        .any(|p| matches_glob(&expand(p, ctx), &word));
+                if !matched {
+                    continue;
+                }
+                status = interpret(&arm.body, ctx);
+                match arm.terminator {
+                    CaseTerminator::Break => break,
+                    CaseTerminator::FallThrough => fall_through = true,
+                    CaseTerminator::Continue => fall_through = false,
+                }
+            }
+            status
+        }
+        Ast::Function { name, body, .. } => {
+            ctx.functions
+                .insert(name.clone(), Rc::new((**body).clone()));
+            0
+        }
+        Ast::Test { expression, .. } => match conditional::evaluate(expression, ctx) {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(e) => {
+                eprintln!("tinysh: [[: {e}");
+                2
+            }
+        },
+    };
+    ctx.last_status = status;
+    status
+}
+
+fn run_loop(ctx: &mut Context, body: impl FnOnce(&mut Context) -> i32) -> i32 {
+    ctx.loop_depth += 1;
+    let status = body(ctx);
+    ctx.loop_depth -= 1;
+    status
+}
+
+/// Consumes a pending `break`/`continue` aimed at the innermost loop and
+/// tells whether that loop has to stop.
+fn end_of_iteration(ctx: &mut Context) -> bool {
+    if ctx.exit.is_some() || ctx.returning.is_some() {
+        return true;
+    }
+    match ctx.loop_control.take() {
+        None | Some(LoopControl::Continue(1)) => false,
+        Some(LoopControl::Break(1)) => true,
+        Some(LoopControl::Break(n)) => {
+            ctx.loop_control = Some(LoopControl::Break(n - 1));
+            true
+        }
+        Some(LoopControl::Continue(n)) => {
+            ctx.loop_control = Some(LoopControl::Continue(n - 1));
+            true
+        }
+    }
+}
+
+/// Runs `body` with `args` as positional parameters and a fresh frame for
+/// `local`; loops of the caller cannot be left with `break` from inside.
+pub fn call_function(ctx: &mut Context, body: &Ast, args: &[String]) -> i32 {
+    let arguments = std::mem::replace(&mut ctx.arguments, args.to_vec());
+    let loop_depth = std::mem::take(&mut ctx.loop_depth);
+    ctx.locals.push(HashMap::new());
+    let status = interpret(body, ctx);
+    let status = ctx.returning.take().unwrap_or(status);
+    for (name, value) in ctx.locals.pop().unwrap_or_default() {
+        match value {
+            Some(v) => ctx.variables.insert(name, v),
+            None => ctx.variables.remove(&name),
+        };
+    }
+    ctx.loop_depth = loop_depth;
+    ctx.arguments = arguments;
+    status
+}
+
+/// Quotes a word that would not come back unchanged from the lexer, so that
+/// it can be put after the value of an alias or inserted by completion.
+pub fn quote_word(word: &str) -> String {
+    if word
+        .chars()
+        .all(|c| c.is_alphanumeric() || "_-./~$=:,+%@{}".contains(c))
+    {
+        word.to_string()
+    } else {
+        format!("\"{word}\"")
+    }
+}
+
+/// Runs the value of an alias followed by the other words of the command.
+fn run_alias(ctx: &mut Context, name: &str, value: &str, args: &[String]) -> i32 {
+    let mut source = value.to_string();
+    for arg in args {
+        source.push(' ');
+        source += &quote_word(arg);
+    }
+    ctx.expanding_aliases.push(name.to_string());
+    let status = run_source(&source, ctx);
+    ctx.expanding_aliases.pop();
+    status
+}
+
+fn run_command(ctx: &mut Context, words: &[String]) -> i32 {
+    if let Some((name, args)) = words.split_first() {
+        if !ctx.expanding_aliases.contains(name) {
+            if let Some(value) = ctx.alias(name).map(|value| value.to_string()) {
+                return run_alias(ctx, name, &value, args);
+            }
+        }
+    }
+    let argv = expand_words(words, ctx);
+    let Some(name) = argv.first() else {
+        return 0;
+    };
+    if argv.len() == 1 {
+        if let Some((var, value)) = name.split_once('=') {
+            if is_a_valid_name(var) {
+                ctx.set_variable(var, value);
+                return 0;
+            }
+        }
+    }
+    if let Some(body) = ctx.functions.get(name).cloned() {
+        return call_function(ctx, &body, &argv[1..]);
+    }
+    match get_builtin(name) {
+        Some(builtin) => builtin(ctx, &argv[1..]),
+        None => spawn(&argv),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{lexing::lexer::Lexer, parsing::parser::init_calc_parser};
+
+    use super::{interpret, is_a_valid_name, Context};
+
+    fn run(str: &str, ctx: &mut Context) -> i32 {
+        let data = Lexer {
+            str: str.to_string(),
+        }
+        .lex_spanned();
+        let ast = init_calc_parser(str, &data).parse().unwrap();
+        interpret(&ast, ctx)
+    }
+
+    #[test]
+    fn test_valid_name() {
+        assert!(is_a_valid_name("_a1"));
+        assert!(!is_a_valid_name("1a"));
+        assert!(!is_a_valid_name(""));
+    }
+
+    #[test]
+    fn test_and_or() {
+        let ctx = &mut Context::new();
+        assert_eq!(run("true && false", ctx), 1);
+        assert_eq!(run("false || true", ctx), 0);
+        assert_eq!(run("false && true", ctx), 1);
+        assert_eq!(run("true; false", ctx), 1);
+        assert_eq!(ctx.last_status, 1);
+    }
+
+    #[test]
+    fn test_assignment() {
+        let ctx = &mut Context::new();
+        assert_eq!(run("a=1; b=$a", ctx), 0);
+        assert_eq!(ctx.get_variable("b"), Some("1".to_string()));
+        assert_eq!(run("c=x\\ \\$a\\\\$a\\;; d=\"\\u \\$a\"", ctx), 0);
+        assert_eq!(ctx.get_variable("c"), Some("x $a\\1;".to_string()));
+        assert_eq!(ctx.get_variable("d"), Some("\\u $a".to_string()));
+    }
+
+    #[test]
+    fn test_exit_stops_list() {
+        let ctx = &mut Context::new();
+        assert_eq!(run("exit 4; a=1", ctx), 4);
+        assert_eq!(ctx.exit, Some(4));
+        assert_eq!(ctx.get_variable("a"), None);
+    }
+
+    #[test]
+    fn test_if() {
+        let ctx = &mut Context::new();
+        assert_eq!(run("if true; then a=1; else a=2; fi", ctx), 0);
+        assert_eq!(ctx.get_variable("a"), Some("1".to_string()));
+        run(
+            "if false; then a=1; elif false; then a=2; else a=3; fi",
+            ctx,
+        );
+        assert_eq!(ctx.get_variable("a"), Some("3".to_string()));
+        assert_eq!(run("if false; then a=4; fi", ctx), 0);
+        assert_eq!(run("if true; then false; fi", ctx), 1);
+        assert_eq!(ctx.get_variable("a"), Some("3".to_string()));
+    }
+
+    #[test]
+    fn test_group_shares_context() {
+        let ctx = &mut Context::new();
+        assert_eq!(run("{ a=1; b=2; }", ctx), 0);
+        assert_eq!(ctx.get_variable("a"), Some("1".to_string()));
+        assert_eq!(run("{ exit 5; }; a=3", ctx), 5);
+        assert_eq!(ctx.exit, Some(5));
+        assert_eq!(ctx.get_variable("a"), Some("1".to_string()));
+    }
+
+    #[test]
+    fn test_group_redirection() {
+        let ctx = &mut Context::new();
+        let path = std::env::temp_dir().join(format!("tinysh-group-{}", std::process::id()));
+        let command = format!("{{ echo a; echo b; }} > {}", path.display());
+        assert_eq!(run(&command, ctx), 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_while() {
+        let ctx = &mut Context::new();
+        assert_eq!(run("a=0; while false; do a=1; done", ctx), 0);
+        assert_eq!(ctx.get_variable("a"), Some("0".to_string()));
+        run("until test $a = 1110; do a=1$a; done", ctx);
+        assert_eq!(ctx.get_variable("a"), Some("1110".to_string()));
+    }
+
+    #[test]
+    fn test_for() {
+        let ctx = &mut Context::new();
+        run("s=; for x in a b c; do s=$s$x; done", ctx);
+        assert_eq!(ctx.get_variable("s"), Some("abc".to_string()));
+        run("s=; for ((i = 0; i < 3; i++)); do s=$s$i; done", ctx);
+        assert_eq!(ctx.get_variable("s"), Some("012".to_string()));
+        assert_eq!(ctx.loop_depth, 0);
+    }
+
+    #[test]
+    fn test_break_continue() {
+        let ctx = &mut Context::new();
+        run(
+            "s=; for x in a b c; do if test $x = b; then continue; fi; s=$s$x; done",
+            ctx,
+        );
+        assert_eq!(ctx.get_variable("s"), Some("ac".to_string()));
+        run(
+            "s=; for x in a b; do for y in 1 2; do s=$s$x$y; break 2; done; done",
+            ctx,
+        );
+        assert_eq!(ctx.get_variable("s"), Some("a1".to_string()));
+        run(
+            "s=; for x in a b; do for y in 1 2; do s=$s$x$y; continue 2; s=no; done; done",
+            ctx,
+        );
+        assert_eq!(ctx.get_variable("s"), Some("a1b1".to_string()));
+        run("while true; do break; done; s=after", ctx);
+        assert_eq!(ctx.get_variable("s"), Some("after".to_string()));
+        assert_eq!(ctx.loop_control, None);
+    }
+
+    #[test]
+    fn test_case() {
+        let ctx = &mut Context::new();
+        let command = "case $x in a|b*) s=ab;; [0-9]) s=digit;; *) s=other;; esac";
+        run(&format!("x=bee; {command}"), ctx);
+        assert_eq!(ctx.get_variable("s"), Some("ab".to_string()));
+        run(&format!("x=7; {command}"), ctx);
+        assert_eq!(ctx.get_variable("s"), Some("digit".to_string()));
+        run(&format!("x=zz; {command}"), ctx);
+        assert_eq!(ctx.get_variable("s"), Some("other".to_string()));
+        assert_eq!(run("case x in y) false;; esac", ctx), 0);
+    }
+
+    #[test]
+    fn test_case_fall_through() {
+        let ctx = &mut Context::new();
+        run("s=; case a in a) s=1;& b) s=${s}2;; c) s=${s}3;; esac", ctx);
+        assert_eq!(ctx.get_variable("s"), Some("12".to_string()));
+        run(
+            "s=; case a in a) s=1;;& b) s=${s}2;; *) s=${s}3;; esac",
+            ctx,
+        );
+        assert_eq!(ctx.get_variable("s"), Some("13".to_string()));
+    }
+
+    #[test]
+    fn test_function() {
+        let ctx = &mut Context::new();
+        assert_eq!(run("greet() { s=$1-$2-$#; }", ctx), 0);
+        run("greet a b; t=$1", ctx);
+        assert_eq!(ctx.get_variable("s"), Some("a-b-2".to_string()));
+        assert_eq!(ctx.get_variable("t"), Some("".to_string()));
+        run(
+            "function each { for x; do s=$s$x; done; }; s=; each 1 2 3",
+            ctx,
+        );
+        assert_eq!(ctx.get_variable("s"), Some("123".to_string()));
+        run("function wrap() { each $@; }; s=; wrap a b", ctx);
+        assert_eq!(ctx.get_variable("s"), Some("ab".to_string()));
+    }
+
+    #[test]
+    fn test_local_and_return() {
+        let ctx = &mut Context::new();
+        run(
+            "x=outer; f() { local x=inner y; s=$x; return 3; s=unreachable; }",
+            ctx,
+        );
+        assert_eq!(run("f", ctx), 3);
+        assert_eq!(ctx.get_variable("s"), Some("inner".to_string()));
+        assert_eq!(ctx.get_variable("x"), Some("outer".to_string()));
+        assert_eq!(ctx.variables.get("y"), None);
+        assert_eq!(ctx.returning, None);
+        run("g() { for i in 1 2; do return $i; done; }", ctx);
+        assert_eq!(run("g; s=$?", ctx), 0);
+        assert_eq!(ctx.get_variable("s"), Some("1".to_string()));
+        assert_eq!(ctx.loop_depth, 0);
+    }
+
+    #[test]
+    fn test_local_exported_variable() {
+        let ctx = &mut Context::new();
+        let name = format!("TINYSH_LOCAL_{}", std::process::id());
+        std::env::set_var(&name, "global");
+        run(
+            &format!(
+                "f() {{ local {name}=inner; {name}=set; for {name} in a b; do s=${name}; done; }}"
+            ),
+            ctx,
+        );
+        assert_eq!(run("f", ctx), 0);
+        assert_eq!(ctx.get_variable("s"), Some("b".to_string()));
+        assert_eq!(std::env::var(&name), Ok("global".to_string()));
+        assert!(!ctx.variables.contains_key(&name));
+        run(&format!("for {name} in c; do true; done"), ctx);
+        assert_eq!(std::env::var(&name), Ok("c".to_string()));
+        std::env::remove_var(name);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let ctx = &mut Context::new();
+        assert_eq!(run("[[ abc == a* ]] && [ abc != a* ]", ctx), 0);
+        assert_eq!(run("test 1 -gt 2 || [[ x =~ [0-9] ]]", ctx), 1);
+        assert_eq!(run("[[ 1 -lt x ]]", ctx), 2);
+        assert_eq!(run("[ a = a", ctx), 2);
+    }
+
+    #[test]
+    fn test_not() {
+        let ctx = &mut Context::new();
+        assert_eq!(run("! false", ctx), 0);
+        assert_eq!(run("! true || a=1", ctx), 0);
+        assert_eq!(ctx.get_variable("a"), Some("1".to_string()));
+        // `not` is only reserved inside `[[ ]]`
+        assert_eq!(run("[[ not -n \"\" ]]", ctx), 0);
+        run("not() { n=$1; }; not 2", ctx);
+        assert_eq!(ctx.get_variable("n"), Some("2".to_string()));
+    }
+
+    #[test]
+    fn test_aliases() {
+        let ctx = &mut Context::new();
+        let aliases = &mut ctx.config.aliases;
+        aliases.insert("set_a".to_string(), "a=1; b=$a".to_string());
+        aliases.insert("f".to_string(), "f x".to_string());
+        run("f() { s=$#-$1-$2; }", ctx);
+        assert_eq!(run("set_a", ctx), 0);
+        assert_eq!(ctx.get_variable("b"), Some("1".to_string()));
+        run("f \"y z\"", ctx);
+        assert_eq!(ctx.get_variable("s"), Some("2-x-y z".to_string()));
+        assert!(ctx.expanding_aliases.is_empty());
+    }
+}
@@ -0,0 +1,7 @@
+pub mod arithmetic;
+pub mod builtins;
+pub mod conditional;
+pub mod expansion;
+pub mod interpreter;
+pub mod process;
+pub mod script;
@@ -0,0 +1,239 @@
+use std::io::Write;
+
+#[cfg(not(unix))]
+use crate::parsing::ast::Ast;
+
+use super::interpreter::{interpret, Context};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Redirection {
+    /// `> file`, truncates and replaces stdout
+    Output,
+    /// `< file`, replaces stdin
+    Input,
+}
+
+/// Runs an external program and waits for it. A program killed by a signal
+/// reports `128 + signal` like other shells.
+pub fn spawn(argv: &[String]) -> i32 {
+    let _ = std::io::stdout().flush();
+    match std::process::Command::new(&argv[0])
+        .args(&argv[1..])
+        .status()
+    {
+        Ok(status) => match status.code() {
+            Some(code) => code,
+            None => {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::process::ExitStatusExt;
+                    128 + status.signal().unwrap_or(0)
+                }
+                #[cfg(not(unix))]
+                1
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            eprintln!("tinysh: command not found: {}", argv[0]);
+            127
+        }
+        Err(e) => {
+            eprintln!("tinysh: {}: {e}", argv[0]);
+            126
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        fs::File,
+        io::{Read, Write},
+        os::fd::{AsRawFd, FromRawFd},
+    };
+
+    use crate::parsing::ast::Ast;
+
+    use super::{interpret, Context, Redirection};
+
+    /// Forks and runs `f` in the child, which exits with its result. Nothing
+    /// `f` does to the context, the working directory or the file
+    /// descriptors reaches the parent.
+    pub fn fork<F: FnOnce() -> i32>(f: F) -> libc::pid_t {
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            let status = f();
+            let _ = std::io::stdout().flush();
+            let _ = std::io::stderr().flush();
+            unsafe { libc::_exit(status) };
+        }
+        pid
+    }
+
+    pub fn wait(pid: libc::pid_t) -> i32 {
+        if pid < 0 {
+            eprintln!("tinysh: fork: {}", std::io::Error::last_os_error());
+            return 1;
+        }
+        let mut status = 0;
+        unsafe { libc::waitpid(pid, &mut status, 0) };
+        if libc::WIFEXITED(status) {
+            libc::WEXITSTATUS(status)
+        } else if libc::WIFSIGNALED(status) {
+            128 + libc::WTERMSIG(status)
+        } else {
+            1
+        }
+    }
+
+    pub fn subshell(ctx: &mut Context, body: &Ast) -> i32 {
+        let pid = fork(|| {
+            let status = interpret(body, ctx);
+            ctx.exit.unwrap_or(status)
+        });
+        wait(pid)
+    }
+
+    pub fn pipeline(ctx: &mut Context, left: &Ast, right: &Ast) -> i32 {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            eprintln!("tinysh: pipe: {}", std::io::Error::last_os_error());
+            return 1;
+        }
+        let [read, write] = fds;
+        let writer = fork(|| {
+            unsafe {
+                libc::dup2(write, 1);
+                libc::close(read);
+                libc::close(write);
+            }
+            interpret(left, ctx)
+        });
+        let reader = fork(|| {
+            unsafe {
+                libc::dup2(read, 0);
+                libc::close(read);
+                libc::close(write);
+            }
+            interpret(right, ctx)
+        });
+        unsafe {
+            libc::close(read);
+            libc::close(write);
+        }
+        wait(writer);
+        wait(reader)
+    }
+
+    /// Runs `f` in a child reading from `/dev/null`, and returns what it
+    /// printed.
+    pub fn capture<F: FnOnce(&mut Context) -> i32>(ctx: &mut Context, f: F) -> String {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            eprintln!("tinysh: pipe: {}", std::io::Error::last_os_error());
+            return String::new();
+        }
+        let [read, write] = fds;
+        let child = fork(|| {
+            unsafe {
+                let null = libc::open(c"/dev/null".as_ptr(), libc::O_RDONLY);
+                libc::dup2(null, 0);
+                libc::close(null);
+                libc::dup2(write, 1);
+                libc::close(read);
+                libc::close(write);
+            }
+            f(ctx)
+        });
+        unsafe { libc::close(write) };
+        let mut output = String::new();
+        let _ = unsafe { File::from_raw_fd(read) }.read_to_string(&mut output);
+        wait(child);
+        output
+    }
+
+    /// Points stdin or stdout at `path` while `f` runs, then restores it.
+    pub fn redirect<F: FnOnce(&mut Context) -> i32>(
+        ctx: &mut Context,
+        direction: Redirection,
+        path: &str,
+        f: F,
+    ) -> i32 {
+        let (file, fd) = match direction {
+            Redirection::Output => (File::create(path), 1),
+            Redirection::Input => (File::open(path), 0),
+        };
+        let file = match file {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("tinysh: {path}: {e}");
+                return 1;
+            }
+        };
+        let _ = std::io::stdout().flush();
+        // keep the original out of the way of the programs we start
+        let saved = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10) };
+        unsafe { libc::dup2(file.as_raw_fd(), fd) };
+        drop(file);
+        let status = f(ctx);
+        let _ = std::io::stdout().flush();
+        unsafe {
+            libc::dup2(saved, fd);
+            libc::close(saved);
+        }
+        status
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{capture, fork, pipeline, redirect, subshell, wait};
+
+/// Without `fork` a subshell runs on a copy of the context and puts the
+/// working directory back afterwards.
+#[cfg(not(unix))]
+pub fn subshell(ctx: &mut Context, body: &Ast) -> i32 {
+    let cwd = std::env::current_dir().ok();
+    let mut child = ctx.clone();
+    let status = interpret(body, &mut child);
+    if let Some(cwd) = cwd {
+        let _ = std::env::set_current_dir(cwd);
+    }
+    child.exit.unwrap_or(status)
+}
+
+#[cfg(not(unix))]
+pub fn pipeline(_ctx: &mut Context, _left: &Ast, _right: &Ast) -> i32 {
+    eprintln!("tinysh: pipelines are only supported on unix");
+    1
+}
+
+/// Without `fork` nothing can be kept from the shell, so nothing is run.
+#[cfg(not(unix))]
+pub fn capture<F: FnOnce(&mut Context) -> i32>(_ctx: &mut Context, _f: F) -> String {
+    String::new()
+}
+
+#[cfg(not(unix))]
+pub fn redirect<F: FnOnce(&mut Context) -> i32>(
+    _ctx: &mut Context,
+    _direction: Redirection,
+    _path: &str,
+    _f: F,
+) -> i32 {
+    eprintln!("tinysh: redirections are only supported on unix");
+    1
+}
+
+#[cfg(test)]
+mod test {
+    use super::spawn;
+
+    #[test]
+    fn test_spawn() {
+        assert_eq!(spawn(&["true".to_string()]), 0);
+        assert_eq!(spawn(&["false".to_string()]), 1);
+        assert_eq!(spawn(&["tinysh-does-not-exist".to_string()]), 127);
+    }
+}
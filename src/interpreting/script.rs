@@ -0,0 +1,230 @@
+use std::{
+    io::{IsTerminal, Read},
+    path::PathBuf,
+};
+
+use crate::{
+    lexing::lexer::Lexer,
+    parsing::{error::ParseError, parser::init_calc_parser},
+};
+
+use super::interpreter::{interpret, Context};
+
+/// Lexes, parses and runs a whole piece of shell source such as a script;
+/// a syntax error is reported on stderr with status 2 and nothing runs.
+pub fn run_source(source: &str, ctx: &mut Context) -> i32 {
+    let tokens = Lexer {
+        str: source.to_string(),
+    }
+    .lex_spanned();
+    match init_calc_parser(source, &tokens).parse() {
+        Ok(ast) => interpret(&ast, ctx),
+        Err(e) => {
+            report(&e, source);
+            ctx.last_status = 2;
+            2
+        }
+    }
+}
+
+/// Runs a startup file in the current shell; a missing file is not an error.
+pub fn load_startup_file(path: &PathBuf, ctx: &mut Context) {
+    if !path.is_file() {
+        return;
+    }
+    match std::fs::read_to_string(path) {
+        Ok(source) => {
+            run_source(&source, ctx);
+        }
+        Err(e) => eprintln!("tinysh: {}: {e}", path.display()),
+    }
+}
+
+/// `~/.tinyshrc`, or `$XDG_CONFIG_HOME/tinysh/rc` (`~/.config` by default)
+/// when there is none. Interactive shells read it on start.
+pub fn rc_file(ctx: &Context) -> Option<PathBuf> {
+    let home = PathBuf::from(ctx.get_variable("HOME")?);
+    let rc = home.join(".tinyshrc");
+    if rc.is_file() {
+        return Some(rc);
+    }
+    let config = match ctx.get_variable("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home.join(".config"),
+    };
+    Some(config.join("tinysh").join("rc"))
+}
+
+/// `~/.tinysh_profile`, read by login shells before the rc file.
+pub fn profile_file(ctx: &Context) -> Option<PathBuf> {
+    Some(PathBuf::from(ctx.get_variable("HOME")?).join(".tinysh_profile"))
+}
+
+/// Prints a syntax error on stderr, in colour only for a terminal.
+pub fn report(error: &ParseError, source: &str) {
+    if std::io::stderr().is_terminal() {
+        eprint!("{}", error.render(source));
+    } else {
+        eprint!("{}", error.render_plain(source));
+    }
+}
+
+/// Reads a single line, byte by byte so that nothing past it is consumed:
+/// commands run in between still find the rest of the input.
+fn read_line(input: &mut impl Read) -> Option<String> {
+    let mut line = Vec::new();
+    let mut byte = [0u8];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) => {
+                line.push(byte[0]);
+                if byte[0] == b'\n' {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+    if line.is_empty() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// Runs commands as they are read from `input`, such as a pipe on stdin,
+/// reading more lines while a command is unfinished. Returns the status the
+/// shell should exit with.
+pub fn run_lines(input: &mut impl Read, ctx: &mut Context) -> i32 {
+    let mut buffer = String::new();
+    while let Some(line) = read_line(input) {
+        buffer += &line;
+        if buffer.ends_with("\\\n") {
+            continue;
+        }
+        let tokens = Lexer {
+            str: buffer.clone(),
+        }
+        .lex_spanned();
+        match init_calc_parser(&buffer, &tokens).parse() {
+            Ok(ast) => {
+                interpret(&ast, ctx);
+            }
+            Err(e) if e.is_incomplete() => continue,
+            Err(e) => {
+                report(&e, &buffer);
+                ctx.last_status = 2;
+            }
+        }
+        buffer.clear();
+        if ctx.exit.is_some() {
+            break;
+        }
+    }
+    if !buffer.trim().is_empty() {
+        // the input ended in the middle of a command
+        run_source(&buffer, ctx);
+    }
+    ctx.exit.unwrap_or(ctx.last_status)
+}
+
+/// Runs the script at `path` with `args` as `$1`, `$2`... and returns the
+/// status the shell should exit with.
+pub fn run_file(path: &str, args: &[String], ctx: &mut Context) -> i32 {
+    let source = match std::fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("tinysh: {path}: {e}");
+            return 127;
+        }
+    };
+    ctx.script_name = Some(path.to_string());
+    ctx.arguments = args.to_vec();
+    run_source(&source, ctx);
+    ctx.exit.unwrap_or(ctx.last_status)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreting::interpreter::Context;
+
+    use super::{load_startup_file, profile_file, rc_file, run_file, run_lines, run_source};
+
+    #[test]
+    fn test_run_file() {
+        let path = std::env::temp_dir().join(format!("tinysh-script-{}.sh", std::process::id()));
+        std::fs::write(
+            &path,
+            "#!/usr/bin/env tinysh\n# joins its arguments\nfor a; do\n  s=$s$a # inline\ndone\nname=$0\nfalse\n",
+        )
+        .unwrap();
+        let path_str = path.to_str().unwrap();
+        let mut ctx = Context::new();
+        let args = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(run_file(path_str, &args, &mut ctx), 1);
+        assert_eq!(ctx.get_variable("s"), Some("ab".to_string()));
+        assert_eq!(ctx.get_variable("name"), Some(path_str.to_string()));
+
+        std::fs::write(&path, "exit 7\ns=unreachable\n").unwrap();
+        assert_eq!(run_file(path_str, &[], &mut ctx), 7);
+        assert_eq!(ctx.get_variable("s"), Some("ab".to_string()));
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(run_file("/nonexistent/tinysh.sh", &[], &mut ctx), 127);
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let mut ctx = Context::new();
+        assert_eq!(run_source("s=1; if true; then", &mut ctx), 2);
+        assert_eq!(ctx.get_variable("s"), None);
+    }
+
+    #[test]
+    fn test_run_lines() {
+        let mut ctx = Context::new();
+        let mut input = "a=1\nif true\nthen b=$a\nfi\ntest 1 \\\n= 1 && c=2\n".as_bytes();
+        assert_eq!(run_lines(&mut input, &mut ctx), 0);
+        assert_eq!(ctx.get_variable("b"), Some("1".to_string()));
+        assert_eq!(ctx.get_variable("c"), Some("2".to_string()));
+
+        let mut input = "a=2 )\nexit 4\na=3\n".as_bytes();
+        assert_eq!(run_lines(&mut input, &mut ctx), 4);
+        assert_eq!(ctx.get_variable("a"), Some("1".to_string()));
+
+        let mut ctx = Context::new();
+        let mut input = "a=1\nwhile true; do".as_bytes();
+        assert_eq!(run_lines(&mut input, &mut ctx), 2);
+    }
+
+    #[test]
+    fn test_startup_files() {
+        let home = std::env::temp_dir().join(format!("tinysh-home-{}", std::process::id()));
+        std::fs::create_dir_all(home.join("config/tinysh")).unwrap();
+        let mut ctx = Context::new();
+        ctx.variables
+            .insert("HOME".to_string(), home.to_str().unwrap().to_string());
+        ctx.variables.insert(
+            "XDG_CONFIG_HOME".to_string(),
+            home.join("config").to_str().unwrap().to_string(),
+        );
+        assert_eq!(rc_file(&ctx), Some(home.join("config/tinysh/rc")));
+        std::fs::write(
+            home.join("config/tinysh/rc"),
+            "greet() { g=hi; }\nalias hello=\"greet; h=there\"\n",
+        )
+        .unwrap();
+        load_startup_file(&rc_file(&ctx).unwrap(), &mut ctx);
+        assert!(ctx.functions.contains_key("greet"));
+        run_source("hello", &mut ctx);
+        assert_eq!(ctx.get_variable("g"), Some("hi".to_string()));
+        assert_eq!(ctx.get_variable("h"), Some("there".to_string()));
+
+        std::fs::write(home.join(".tinyshrc"), "a=1").unwrap();
+        assert_eq!(rc_file(&ctx), Some(home.join(".tinyshrc")));
+        assert_eq!(profile_file(&ctx), Some(home.join(".tinysh_profile")));
+        load_startup_file(&profile_file(&ctx).unwrap(), &mut ctx);
+        assert_eq!(ctx.last_status, 0);
+        std::fs::remove_dir_all(home).unwrap();
+    }
+}
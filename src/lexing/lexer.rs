@@ -1,9 +1,9 @@
 use std::str::Chars;
 
-use super::token::Token;
+use super::token::{CaseTerminator, Keyword, Span, SpannedToken, Token};
 
 pub fn is_an_allowed_character(character: char) -> bool {
-    return character.is_alphanumeric()
+    character.is_alphanumeric()
         || character == '&'
         || character == '>'
         || character == '|'
@@ -15,7 +15,64 @@ pub fn is_an_allowed_character(character: char) -> bool {
         || character == ')'
         || character == '.'
         || character == '"'
-        || character == ' ';
+        || character == ' '
+        || character == '\n'
+        || character == ';'
+        || character == '{'
+        || character == '}'
+        || character == '#'
+        || is_a_word_character(character)
+}
+
+/// Characters which can appear inside a bare word such as a path, a flag
+/// or an assignment (`./a.out`, `--all`, `NAME=value`, `$HOME`).
+pub fn is_a_word_character(character: char) -> bool {
+    character.is_alphanumeric()
+        || character == '_'
+        || character == '.'
+        || character == '/'
+        || character == '-'
+        || character == '='
+        || character == '$'
+        || character == '*'
+        || character == '?'
+        || character == '+'
+        || character == ','
+        || character == ':'
+        || character == '@'
+        || character == '['
+        || character == ']'
+        || character == '!'
+        || character == '^'
+}
+
+/// Whether a token following `tokens` is where a command starts, which is
+/// the only place a reserved word is one.
+fn starts_command(tokens: &[SpannedToken]) -> bool {
+    match tokens.split_last() {
+        None => true,
+        Some((last, before)) => match &last.token {
+            // `)` also ends the patterns of a `case` arm
+            Token::Pipe
+            | Token::And
+            | Token::Or
+            | Token::Semicolon
+            | Token::CaseEnd(_)
+            | Token::LPar
+            | Token::RPar
+            | Token::LBrace => true,
+            Token::Keyword(k) => k.opens_list() && starts_command(before),
+            _ => false,
+        },
+    }
+}
+
+/// Whether a word starts after `before`, at the start of the input or
+/// after whitespace or an operator, where `#` begins a comment.
+fn starts_word(before: &str) -> bool {
+    before.chars().next_back().is_none_or(|c| {
+        c.is_whitespace() || matches!(c, ';' | '|' | '&' | '(' | ')' | '<' | '>' | '{' | '}')
+    })
 }
 
 #[derive(Clone, PartialEq)]
@@ -25,24 +82,18 @@ pub struct Lexer {
 
 impl Lexer {
     fn lex_int(&self, chars: &mut Chars, current_char: char) -> i64 {
-        let a = self.clone().lex_raddix(chars, Some(current_char));
-        let err: Result<i64, std::num::ParseIntError> = a.parse();
-        match err {
-            Err(e) => {
-                println!("{e}");
-                0
-            }
-            Ok(t) => t,
-        }
+        let a = self.lex_raddix(chars, Some(current_char));
+        // too large for an `i64`, the caller keeps it as a word
+        a.parse().unwrap_or(0)
     }
 
-    fn lex_raddix(self, chars: &mut Chars, mut current_char: Option<char>) -> String {
+    fn lex_raddix(&self, chars: &mut Chars, mut current_char: Option<char>) -> String {
         let mut str: String = String::new();
         str += &*current_char.unwrap().to_string();
         let mut peekable = chars.clone().peekable();
-        while current_char != None {
+        while current_char.is_some() {
             current_char = peekable.next();
-            if current_char == None {
+            if current_char.is_none() {
                 break;
             }
             if !current_char.unwrap().is_ascii_digit() {
@@ -54,29 +105,44 @@ impl Lexer {
         str
     }
 
-    fn lex_float(self, whole_side: i64, chars: &mut Chars) -> f64 {
+    fn lex_float(&self, whole_side: i64, chars: &mut Chars) -> f64 {
         let current_char_options = chars.next();
-        let current_char = match current_char_options {
-            Some(t) => t,
-            None => '0',
-        };
+        let current_char = current_char_options.unwrap_or('0');
         let a = self.lex_raddix(chars, Some(current_char));
-        let f = (&*(whole_side.to_string().as_str().to_owned() + "." + a.as_str())).parse();
+        let f = (whole_side.to_string() + "." + a.as_str()).parse();
         if f.is_err() {
             return f64::NAN;
         }
         f.unwrap()
     }
-    fn lex_string(self, chars: &mut Chars, mut current_char: Option<char>) -> String {
+    fn lex_string(&self, chars: &mut Chars, mut current_char: Option<char>) -> String {
         let mut str: String = String::new();
         str += &*current_char.unwrap().to_string();
         let mut peekable = chars.clone().peekable();
-        while current_char != None {
+        while current_char.is_some() {
             current_char = peekable.next();
-            if current_char == None {
+            if current_char.is_none() {
                 break;
             }
-            if !(current_char.unwrap().is_alphanumeric() || current_char.unwrap() == '_') {
+            if current_char == Some('{') && str.ends_with('$') {
+                // `${NAME}` stays inside the word
+                for c in chars.by_ref() {
+                    str.push(c);
+                    if c == '}' {
+                        break;
+                    }
+                }
+                peekable = chars.clone().peekable();
+                continue;
+            }
+            if current_char == Some('#') {
+                // only a word's first `#` starts a comment, and `$#` is a
+                // variable
+                chars.next();
+                str.push('#');
+                continue;
+            }
+            if !is_a_word_character(current_char.unwrap()) {
                 break;
             }
             current_char = chars.next();
@@ -86,36 +152,139 @@ impl Lexer {
         str
     }
     pub fn lex(&self) -> Vec<Token> {
+        self.lex_spanned().into_iter().map(|t| t.token).collect()
+    }
+
+    pub fn lex_spanned(&self) -> Vec<SpannedToken> {
         let mut char_iter = self.str.chars();
-        let mut vec = Vec::new();
+        let mut vec: Vec<SpannedToken> = Vec::new();
         let mut char = char_iter.next();
         let mut quote = 0;
-        while char != None {
-            let v = match char.unwrap() {
+        let mut line = 1;
+        let mut line_start = 0;
+        while let Some(current) = char {
+            let start = self.str.len() - char_iter.as_str().len() - current.len_utf8();
+            let mut token_start = start;
+            let v = match current {
+                // a backslash at the end of a line joins it with the next one
+                '\\' if char_iter.clone().next() == Some('\n') => {
+                    char_iter.next();
+                    line += 1;
+                    line_start = start + 2;
+                    Token::Null
+                }
+                // inside quotes, the escapes are left to the expansion; `\"`
+                // does not close the quote
+                '\\' if quote % 2 == 1 => match char_iter.clone().next() {
+                    Some(c @ ('"' | '\\')) => {
+                        char_iter.next();
+                        Token::Identifier(format!("\\{c}"))
+                    }
+                    _ => Token::Identifier("\\".to_string()),
+                },
+                // outside, it makes the next character part of the word, and
+                // `\$` and `\\` stay escaped for the expansion
+                '\\' => match char_iter.next() {
+                    Some(c @ ('$' | '\\')) => Token::Identifier(format!("\\{c}")),
+                    Some(c) => Token::Identifier(c.to_string()),
+                    None => Token::Identifier("\\\\".to_string()),
+                },
                 p if !is_an_allowed_character(p) => Token::Null,
                 '>' => Token::LeftRedirection,
                 '<' => Token::RightRedirection,
-                '|' => Token::Pipe,
+                '|' => match vec.last() {
+                    Some(SpannedToken {
+                        token: Token::Pipe,
+                        span,
+                    }) if span.end == start => {
+                        token_start = span.start;
+                        vec.pop();
+                        Token::Or
+                    }
+                    _ => Token::Pipe,
+                },
+                ';' => match vec.last() {
+                    Some(SpannedToken {
+                        token: Token::Semicolon,
+                        span,
+                    }) if span.end == start && &self.str[span.start..span.end] == ";" => {
+                        token_start = span.start;
+                        vec.pop();
+                        Token::CaseEnd(CaseTerminator::Break)
+                    }
+                    _ => Token::Semicolon,
+                },
                 '~' => Token::Tilde,
                 ')' => Token::RPar,
                 '(' => Token::LPar,
+                '}' => Token::RBrace,
+                '{' => Token::LBrace,
                 '-' => Token::Dash,
                 '"' => {
                     quote += 1;
                     Token::Quote
                 }
-                ' ' => {
-                    if quote % 2 == 1 {
-                        Token::Whitespace
-                    } else {
-                        Token::Null
+                ' ' | '\n' if quote % 2 == 1 => Token::Whitespace,
+                '#' if quote % 2 == 1 || !starts_word(&self.str[..start]) => {
+                    Token::Identifier("#".to_string())
+                }
+                '#' => {
+                    // a comment runs until the end of the line, which is
+                    // still lexed as a separator
+                    while char_iter.clone().next().is_some_and(|c| c != '\n') {
+                        char_iter.next();
                     }
+                    Token::Null
                 }
+                ' ' => Token::Null,
+                '\n' => match vec.last().map(|t| &t.token) {
+                    // a line break right after an operator continues the command
+                    None
+                    | Some(Token::Pipe)
+                    | Some(Token::And)
+                    | Some(Token::Or)
+                    | Some(Token::LPar)
+                    | Some(Token::LBrace)
+                    | Some(Token::Semicolon)
+                    | Some(Token::CaseEnd(_)) => Token::Null,
+                    // and so does one after a reserved word, unless it is
+                    // only an argument such as in `echo in`; outside `[[ ]]`,
+                    // `not` is the name of a command
+                    Some(Token::Keyword(k))
+                        if !k.is_closing()
+                            && *k != Keyword::Not
+                            && starts_command(&vec[..vec.len() - 1]) =>
+                    {
+                        Token::Null
+                    }
+                    _ => Token::Semicolon,
+                },
                 '&' => {
                     let v = vec.pop();
                     match v {
                         None => Token::PreAnd,
-                        Some(Token::PreAnd) => Token::And,
+                        Some(SpannedToken {
+                            token: Token::PreAnd,
+                            span,
+                        }) => {
+                            token_start = span.start;
+                            Token::And
+                        }
+                        // `;&` and `;;&` end a `case` arm
+                        Some(SpannedToken {
+                            token: Token::Semicolon,
+                            span,
+                        }) if span.end == start && &self.str[span.start..span.end] == ";" => {
+                            token_start = span.start;
+                            Token::CaseEnd(CaseTerminator::FallThrough)
+                        }
+                        Some(SpannedToken {
+                            token: Token::CaseEnd(CaseTerminator::Break),
+                            span,
+                        }) if span.end == start => {
+                            token_start = span.start;
+                            Token::CaseEnd(CaseTerminator::Continue)
+                        }
                         Some(p) => {
                             vec.push(p);
                             Token::PreAnd
@@ -125,56 +294,78 @@ impl Lexer {
                 ch => {
                     if ch.is_numeric() {
                         let a = self.lex_int(&mut char_iter, ch);
+                        let raw = &self.str[start..self.str.len() - char_iter.as_str().len()];
                         let mut next = char_iter.clone().peekable();
                         match next.peek() {
+                            // leading zeros matter to words like `chmod 0755`,
+                            // and numbers too large stay as typed
+                            _ if (raw.len() > 1 && raw.starts_with('0'))
+                                || raw.parse::<i64>().is_err() =>
+                            {
+                                Token::Identifier(raw.to_string())
+                            }
                             Some(p) if *p == '.' => {
                                 char_iter.next();
-                                let f = self.clone().lex_float(a, &mut char_iter);
+                                let f = self.lex_float(a, &mut char_iter);
                                 Token::Float(f)
                             }
                             _ => Token::Int(a),
                         }
-                    } else if ch.is_alphabetic() {
-                        let str = self.clone().lex_string(&mut char_iter, Some(ch));
+                    } else if ch == '.'
+                        && char_iter.clone().next().is_some_and(|c| c.is_ascii_digit())
+                    {
+                        let f = self.lex_float(0, &mut char_iter);
+                        Token::Float(f)
+                    } else if ch.is_alphabetic() || is_a_word_character(ch) {
+                        let str = self.lex_string(&mut char_iter, Some(ch));
                         match str.as_str() {
                             "false" => Token::Bool(false),
                             "true" => Token::Bool(true),
                             "or" => Token::Or,
                             "and" => Token::And,
-                            _ => Token::Identifier(str),
+                            s => match Keyword::from_word(s) {
+                                Some(k) => Token::Keyword(k),
+                                None => Token::Identifier(str),
+                            },
                         }
-                    } else if ch == '.' {
-                        let f = self.clone().lex_float(0, &mut char_iter);
-                        Token::Float(f)
                     } else {
                         Token::Null
                     }
                 }
             };
             if v != Token::Null {
-                vec.push(v)
+                let end = self.str.len() - char_iter.as_str().len();
+                let column = self.str[line_start..token_start].chars().count() + 1;
+                vec.push(SpannedToken::new(
+                    v,
+                    Span::new(token_start, end, line, column),
+                ))
+            }
+            if current == '\n' {
+                line += 1;
+                line_start = start + 1;
             }
             char = char_iter.next();
         }
-        let mut final_vec = Vec::new();
         vec.into_iter()
-            .filter(|x| &Token::PreAnd != x)
-            .for_each(|f| final_vec.push(f));
-        final_vec
+            .filter(|x| Token::PreAnd != x.token)
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod test {
 
-    use crate::lexing::token::Token;
+    use crate::lexing::token::{CaseTerminator, Keyword, Span, SpannedToken, Token};
 
     use super::{is_an_allowed_character, Lexer};
 
     #[test]
+    #[allow(clippy::clone_on_copy)]
     pub fn test_allowed() {
         let expected = vec![
-            'c', 'l', 'm', '&', '|', '>', '<', '-', '_', '0', '~', '(', ')', '.', ' ', '"', ' ',
+            'c', 'l', 'm', '&', '|', '>', '<', '-', '_', '0', '~', '^', '(', ')', '.', ' ', '$',
+            '"', ' ',
         ];
         let value = vec![
             'c', 'l', 'm', '&', '|', '>', '<', '-', '_', '0', '~', '^', '(', ')', '%', '.', ' ',
@@ -294,6 +485,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     pub fn test_lex_float_error_nan() {
         let mut chars = "ss".chars();
         let _first_char = chars.next().unwrap();
@@ -315,4 +507,222 @@ mod test {
         .lex_float(0, &mut chars);
         assert_eq!(value, expected);
     }
+
+    #[test]
+    pub fn test_spans() {
+        let expected = vec![
+            SpannedToken::new(Token::Identifier("ls".to_string()), Span::new(0, 2, 1, 1)),
+            SpannedToken::new(Token::Dash, Span::new(3, 4, 1, 4)),
+            SpannedToken::new(Token::Identifier("la".to_string()), Span::new(4, 6, 1, 5)),
+            SpannedToken::new(Token::And, Span::new(7, 9, 1, 8)),
+            SpannedToken::new(Token::Float(1.5), Span::new(10, 13, 1, 11)),
+        ];
+        let value = Lexer {
+            str: "ls -la && 1.5".to_string(),
+        };
+        assert_eq!(value.lex_spanned(), expected);
+    }
+
+    #[test]
+    pub fn test_spans_multiline() {
+        let expected = vec![
+            SpannedToken::new(Token::Identifier("a".to_string()), Span::new(0, 1, 1, 1)),
+            SpannedToken::new(Token::Semicolon, Span::new(1, 2, 1, 2)),
+            SpannedToken::new(Token::Pipe, Span::new(4, 5, 2, 3)),
+        ];
+        let value = Lexer {
+            str: "a\n  |".to_string(),
+        };
+        assert_eq!(value.lex_spanned(), expected);
+    }
+
+    #[test]
+    pub fn test_lex_words() {
+        let expected = vec![
+            Token::Identifier("./a.out".to_string()),
+            Token::Identifier("/tmp/x-y".to_string()),
+            Token::Identifier("NAME=value".to_string()),
+            Token::Identifier("$HOME".to_string()),
+            Token::Identifier("0755".to_string()),
+            Token::Float(0.5),
+            Token::Identifier("99999999999999999999".to_string()),
+        ];
+        let value = Lexer {
+            str: "./a.out /tmp/x-y NAME=value $HOME 0755 .5 99999999999999999999".to_string(),
+        };
+        assert_eq!(value.lex(), expected);
+    }
+
+    #[test]
+    pub fn test_lex_separators() {
+        let expected = vec![
+            Token::Identifier("a".to_string()),
+            Token::Semicolon,
+            Token::Identifier("b".to_string()),
+            Token::Or,
+            Token::Identifier("c".to_string()),
+            Token::Semicolon,
+            Token::Identifier("d".to_string()),
+            Token::Pipe,
+            Token::Identifier("e".to_string()),
+        ];
+        let value = Lexer {
+            str: "a; b || c\nd |\ne".to_string(),
+        };
+        assert_eq!(value.lex(), expected);
+    }
+
+    #[test]
+    pub fn test_lex_braces() {
+        let expected = vec![
+            Token::LBrace,
+            Token::Identifier("echo".to_string()),
+            Token::Identifier("a${B}c".to_string()),
+            Token::Semicolon,
+            Token::RBrace,
+        ];
+        let value = Lexer {
+            str: "{\necho a${B}c; }".to_string(),
+        };
+        assert_eq!(value.lex(), expected);
+    }
+
+    #[test]
+    pub fn test_lex_keywords() {
+        let expected = vec![
+            Token::Keyword(Keyword::If),
+            Token::Identifier("a".to_string()),
+            Token::Semicolon,
+            Token::Keyword(Keyword::Then),
+            Token::Identifier("b".to_string()),
+            Token::Semicolon,
+            Token::Keyword(Keyword::Fi),
+            Token::Semicolon,
+            Token::Identifier("iff".to_string()),
+        ];
+        let value = Lexer {
+            str: "if a\nthen\nb\nfi\niff".to_string(),
+        };
+        assert_eq!(value.lex(), expected);
+    }
+
+    #[test]
+    pub fn test_lex_keyword_arguments() {
+        let expected = vec![
+            Token::Identifier("echo".to_string()),
+            Token::Keyword(Keyword::In),
+            Token::Semicolon,
+            Token::Identifier("echo".to_string()),
+            Token::Keyword(Keyword::Then),
+            Token::Semicolon,
+            Token::Keyword(Keyword::While),
+            Token::Keyword(Keyword::Bang),
+            Token::Identifier("a".to_string()),
+            Token::Semicolon,
+            Token::Identifier("echo".to_string()),
+            Token::Keyword(Keyword::Bang),
+            Token::Semicolon,
+            Token::Keyword(Keyword::Not),
+            Token::Semicolon,
+            Token::Identifier("b".to_string()),
+        ];
+        let value = Lexer {
+            str: "echo in\necho then\nwhile\n!\na\necho !\nnot\nb".to_string(),
+        };
+        assert_eq!(value.lex(), expected);
+    }
+
+    #[test]
+    pub fn test_lex_case_terminators() {
+        let expected = vec![
+            Token::Identifier("a".to_string()),
+            Token::CaseEnd(CaseTerminator::Break),
+            Token::Identifier("b".to_string()),
+            Token::CaseEnd(CaseTerminator::FallThrough),
+            Token::Identifier("[a-z]*".to_string()),
+            Token::CaseEnd(CaseTerminator::Continue),
+            Token::Keyword(Keyword::Esac),
+            Token::Semicolon,
+            Token::Semicolon,
+            Token::Identifier("c".to_string()),
+        ];
+        let value = Lexer {
+            str: "a;;\nb;&\n[a-z]*;;&\nesac\n;c".to_string(),
+        };
+        assert_eq!(value.lex(), expected);
+    }
+
+    #[test]
+    pub fn test_lex_comments() {
+        let expected = vec![
+            Token::Identifier("a".to_string()),
+            Token::Semicolon,
+            Token::Identifier("b$#".to_string()),
+            Token::Quote,
+            Token::Identifier("#".to_string()),
+            Token::Quote,
+        ];
+        let value = Lexer {
+            str: "#!/usr/bin/env tinysh\na # comment\nb$# \"#\" #".to_string(),
+        };
+        assert_eq!(value.lex(), expected);
+        // `#` inside a word is part of it
+        let expected = vec![
+            Token::Identifier("echo".to_string()),
+            Token::Identifier("a#b".to_string()),
+            Token::Quote,
+            Token::Identifier("c".to_string()),
+            Token::Quote,
+            Token::Identifier("#".to_string()),
+            Token::Identifier("d".to_string()),
+            Token::Semicolon,
+        ];
+        let value = Lexer {
+            str: "echo a#b \"c\"#d;#e".to_string(),
+        };
+        assert_eq!(value.lex(), expected);
+    }
+
+    #[test]
+    pub fn test_lex_line_continuation() {
+        let value = Lexer {
+            str: "a \\\n b".to_string(),
+        };
+        let expected = vec![
+            SpannedToken::new(Token::Identifier("a".to_string()), Span::new(0, 1, 1, 1)),
+            SpannedToken::new(Token::Identifier("b".to_string()), Span::new(5, 6, 2, 2)),
+        ];
+        assert_eq!(value.lex_spanned(), expected);
+        let value = Lexer {
+            str: "\\u\\$\\\nb".to_string(),
+        };
+        let expected = vec![
+            Token::Identifier("u".to_string()),
+            Token::Identifier("\\$".to_string()),
+            Token::Identifier("b".to_string()),
+        ];
+        assert_eq!(value.lex(), expected);
+    }
+
+    #[test]
+    pub fn test_lex_backslash() {
+        let value = Lexer {
+            str: "a\\ b\\;c \"\\\" \\$\"\\\\".to_string(),
+        };
+        let expected = vec![
+            Token::Identifier("a".to_string()),
+            Token::Identifier(" ".to_string()),
+            Token::Identifier("b".to_string()),
+            Token::Identifier(";".to_string()),
+            Token::Identifier("c".to_string()),
+            Token::Quote,
+            Token::Identifier("\\\"".to_string()),
+            Token::Whitespace,
+            Token::Identifier("\\".to_string()),
+            Token::Identifier("$".to_string()),
+            Token::Quote,
+            Token::Identifier("\\\\".to_string()),
+        ];
+        assert_eq!(value.lex(), expected);
+    }
 }
@@ -16,11 +16,49 @@ pub enum Token {
     RightRedirection,
     LPar,
     RPar,
+    LBrace,
+    RBrace,
     Tilde,
     Dash,
+    Semicolon,
+    Keyword(Keyword),
+    CaseEnd(CaseTerminator),
     Null,
 }
 
+/// Reserved words; they only act as keywords where a command could start,
+/// anywhere else they are plain words (`echo if`).
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+pub enum Keyword {
+    If,
+    Then,
+    Elif,
+    Else,
+    Fi,
+    While,
+    Until,
+    For,
+    In,
+    Do,
+    Done,
+    Case,
+    Esac,
+    Function,
+    OpenTest,
+    CloseTest,
+    Bang,
+    Not,
+}
+
+/// What ends a `case` arm: `;;` stops, `;&` runs the next arm's list
+/// without testing it and `;;&` goes on testing the following patterns.
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+pub enum CaseTerminator {
+    Break,
+    FallThrough,
+    Continue,
+}
+
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
 pub enum TokenType {
     Int,
@@ -36,10 +74,164 @@ pub enum TokenType {
     RightRedirection,
     LPar,
     RPar,
+    LBrace,
+    RBrace,
     Tilde,
     Dash,
+    Semicolon,
+    Keyword(Keyword),
+    CaseEnd(CaseTerminator),
     Null,
 }
+
+/// Location of a piece of source text: byte offsets `start..end` plus the
+/// 1-based line and column (in characters) of `start`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// Smallest span covering both `self` and `other`. The default span
+    /// (line 0) stands for "nowhere" and leaves the other one untouched.
+    pub fn merge(&self, other: Span) -> Span {
+        if other.line == 0 {
+            return *self;
+        }
+        if self.line == 0 {
+            return other;
+        }
+        let (first, last) = if self.start <= other.start {
+            (*self, other)
+        } else {
+            (other, *self)
+        };
+        Span {
+            start: first.start,
+            end: first.end.max(last.end),
+            line: first.line,
+            column: first.column,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl SpannedToken {
+    pub fn new(token: Token, span: Span) -> SpannedToken {
+        SpannedToken { token, span }
+    }
+}
+
+impl Keyword {
+    pub fn from_word(word: &str) -> Option<Keyword> {
+        match word {
+            "if" => Some(Keyword::If),
+            "then" => Some(Keyword::Then),
+            "elif" => Some(Keyword::Elif),
+            "else" => Some(Keyword::Else),
+            "fi" => Some(Keyword::Fi),
+            "while" => Some(Keyword::While),
+            "until" => Some(Keyword::Until),
+            "for" => Some(Keyword::For),
+            "in" => Some(Keyword::In),
+            "do" => Some(Keyword::Do),
+            "done" => Some(Keyword::Done),
+            "case" => Some(Keyword::Case),
+            "esac" => Some(Keyword::Esac),
+            "function" => Some(Keyword::Function),
+            "[[" => Some(Keyword::OpenTest),
+            "]]" => Some(Keyword::CloseTest),
+            "!" => Some(Keyword::Bang),
+            "not" => Some(Keyword::Not),
+            _ => None,
+        }
+    }
+
+    /// Whether a command can start right after the keyword, as after `;`.
+    pub fn opens_list(&self) -> bool {
+        matches!(
+            self,
+            Keyword::If
+                | Keyword::Then
+                | Keyword::Elif
+                | Keyword::Else
+                | Keyword::While
+                | Keyword::Until
+                | Keyword::Do
+                | Keyword::Bang
+        )
+    }
+
+    /// Whether the keyword ends a compound command rather than expecting a
+    /// list after it.
+    pub fn is_closing(&self) -> bool {
+        matches!(
+            self,
+            Keyword::Fi | Keyword::Done | Keyword::Esac | Keyword::CloseTest
+        )
+    }
+}
+
+impl Display for Keyword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Keyword::If => write!(f, "if"),
+            Keyword::Then => write!(f, "then"),
+            Keyword::Elif => write!(f, "elif"),
+            Keyword::Else => write!(f, "else"),
+            Keyword::Fi => write!(f, "fi"),
+            Keyword::While => write!(f, "while"),
+            Keyword::Until => write!(f, "until"),
+            Keyword::For => write!(f, "for"),
+            Keyword::In => write!(f, "in"),
+            Keyword::Do => write!(f, "do"),
+            Keyword::Done => write!(f, "done"),
+            Keyword::Case => write!(f, "case"),
+            Keyword::Esac => write!(f, "esac"),
+            Keyword::Function => write!(f, "function"),
+            Keyword::OpenTest => write!(f, "[["),
+            Keyword::CloseTest => write!(f, "]]"),
+            Keyword::Bang => write!(f, "!"),
+            Keyword::Not => write!(f, "not"),
+        }
+    }
+}
+
+impl Display for CaseTerminator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaseTerminator::Break => write!(f, ";;"),
+            CaseTerminator::FallThrough => write!(f, ";&"),
+            CaseTerminator::Continue => write!(f, ";;&"),
+        }
+    }
+}
+
 impl Token {
     // add code here
     pub fn to_token_type(&self) -> TokenType {
@@ -57,8 +249,13 @@ impl Token {
             Token::RightRedirection => TokenType::RightRedirection,
             Token::LPar => TokenType::LPar,
             Token::RPar => TokenType::RPar,
+            Token::LBrace => TokenType::LBrace,
+            Token::RBrace => TokenType::RBrace,
             Token::Tilde => TokenType::Tilde,
             Token::Dash => TokenType::Dash,
+            Token::Semicolon => TokenType::Semicolon,
+            Token::Keyword(k) => TokenType::Keyword(*k),
+            Token::CaseEnd(t) => TokenType::CaseEnd(*t),
             _ => TokenType::Null,
         }
     }
@@ -80,19 +277,53 @@ impl Display for Token {
             Token::RightRedirection => write!(f, "<"),
             Token::LPar => write!(f, "("),
             Token::RPar => write!(f, ")"),
+            Token::LBrace => write!(f, "{{"),
+            Token::RBrace => write!(f, "}}"),
             Token::Tilde => write!(f, "~"),
             Token::Dash => write!(f, "-"),
+            Token::Semicolon => write!(f, ";"),
+            Token::Keyword(k) => write!(f, "{k}"),
+            Token::CaseEnd(t) => write!(f, "{t}"),
             _ => write!(f, ""),
         }
     }
 }
 
+impl Display for TokenType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenType::Int => write!(f, "integer"),
+            TokenType::Float => write!(f, "float"),
+            TokenType::Identifier => write!(f, "identifier"),
+            TokenType::Bool => write!(f, "boolean"),
+            TokenType::Quote => write!(f, "`\"`"),
+            TokenType::Whitespace => write!(f, "whitespace"),
+            TokenType::And => write!(f, "`&&`"),
+            TokenType::Or => write!(f, "`or`"),
+            TokenType::Pipe => write!(f, "`|`"),
+            TokenType::LeftRedirection => write!(f, "`>`"),
+            TokenType::RightRedirection => write!(f, "`<`"),
+            TokenType::LPar => write!(f, "`(`"),
+            TokenType::RPar => write!(f, "`)`"),
+            TokenType::LBrace => write!(f, "`{{`"),
+            TokenType::RBrace => write!(f, "`}}`"),
+            TokenType::Tilde => write!(f, "`~`"),
+            TokenType::Dash => write!(f, "`-`"),
+            TokenType::Semicolon => write!(f, "`;`"),
+            TokenType::Keyword(k) => write!(f, "`{k}`"),
+            TokenType::CaseEnd(t) => write!(f, "`{t}`"),
+            TokenType::Null => write!(f, "end of input"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
 
-    use super::{Token, TokenType};
+    use super::{CaseTerminator, Keyword, Span, Token, TokenType};
 
     #[test]
+    #[allow(clippy::useless_conversion)]
     fn to_token_type() {
         let expected = vec![
             TokenType::Int,
@@ -108,8 +339,12 @@ mod test {
             TokenType::RightRedirection,
             TokenType::LPar,
             TokenType::RPar,
+            TokenType::LBrace,
+            TokenType::RBrace,
             TokenType::Tilde,
             TokenType::Dash,
+            TokenType::Semicolon,
+            TokenType::Keyword(Keyword::If),
             TokenType::Null,
             TokenType::Null,
         ];
@@ -128,8 +363,12 @@ mod test {
             Token::RightRedirection,
             Token::LPar,
             Token::RPar,
+            Token::LBrace,
+            Token::RBrace,
             Token::Tilde,
             Token::Dash,
+            Token::Semicolon,
+            Token::Keyword(Keyword::If),
             Token::Null,
             Token::PreAnd,
         ];
@@ -155,8 +394,12 @@ mod test {
             "<".to_string(),
             "(".to_string(),
             ")".to_string(),
+            "{".to_string(),
+            "}".to_string(),
             "~".to_string(),
             "-".to_string(),
+            ";".to_string(),
+            "if".to_string(),
             "".to_string(),
             "".to_string(),
         ];
@@ -175,8 +418,12 @@ mod test {
             Token::RightRedirection,
             Token::LPar,
             Token::RPar,
+            Token::LBrace,
+            Token::RBrace,
             Token::Tilde,
             Token::Dash,
+            Token::Semicolon,
+            Token::Keyword(Keyword::If),
             Token::Null,
             Token::PreAnd,
         ];
@@ -185,4 +432,33 @@ mod test {
             .zip(expected)
             .for_each(|(x, y)| assert_eq!(x.to_string(), y));
     }
+
+    #[test]
+    fn test_span_merge() {
+        let a = Span::new(0, 2, 1, 1);
+        let b = Span::new(5, 9, 1, 6);
+        let expected = Span::new(0, 9, 1, 1);
+        assert_eq!(a.merge(b), expected);
+        assert_eq!(b.merge(a), expected);
+        assert_eq!(expected.len(), 9);
+        assert_eq!(a.merge(Span::default()), a);
+        assert_eq!(Span::default().merge(a), a);
+        assert!(Span::default().is_empty());
+    }
+
+    #[test]
+    fn test_keyword_from_word() {
+        assert_eq!(Keyword::from_word("elif"), Some(Keyword::Elif));
+        assert_eq!(Keyword::from_word("iff"), None);
+        assert!(Keyword::Fi.is_closing());
+        assert!(Keyword::Done.is_closing());
+        assert_eq!(Keyword::from_word("until"), Some(Keyword::Until));
+        assert!(!Keyword::Then.is_closing());
+        assert_eq!(TokenType::Keyword(Keyword::Fi).to_string(), "`fi`");
+        assert!(Keyword::Esac.is_closing());
+        assert_eq!(
+            TokenType::CaseEnd(CaseTerminator::Continue).to_string(),
+            "`;;&`"
+        );
+    }
 }
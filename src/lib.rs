@@ -0,0 +1,10 @@
+pub mod completion;
+pub mod config;
+pub mod git;
+pub mod highlight;
+pub mod history;
+pub mod interpreting;
+pub mod lexing;
+pub mod parsing;
+pub mod prompt;
+pub mod suggestion;
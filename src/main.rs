@@ -1,37 +1,298 @@
+use std::{io::IsTerminal, path::Path, sync::Arc, time::Instant};
+
 use ansi_term::Color;
 use linefeed::{Interface, ReadResult};
 
-use crate::lexing::lexer::Lexer;
+use tinysh::{
+    completion::{spec::default_specs, ShellCompleter},
+    config::{parse_colour, Config},
+    highlight::{self, highlights, paint},
+    history::{expand_history, history_file},
+    interpreting::{
+        interpreter::{interpret, Context},
+        script::{
+            load_startup_file, profile_file, rc_file, report, run_file, run_lines, run_source,
+        },
+    },
+    lexing::{lexer::Lexer, token::SpannedToken},
+    parsing::{ast::Ast, error::ParseError, parser::init_calc_parser},
+    prompt,
+    suggestion::{bind_keys, suggest, Suggestions},
+};
 
-mod lexing;
-mod parsing;
+/// What the command line asks for, once options are taken out.
+#[derive(Default)]
+struct Options {
+    /// `-c string`: run it instead of a script or the prompt
+    command: Option<String>,
+    /// The script and its arguments, or `$0` and the arguments after `-c`
+    operands: Vec<String>,
+    /// `-l`/`--login`, or a `-` in front of the name the shell was started as
+    login: bool,
+    norc: bool,
+    noprofile: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" => match args.next() {
+                Some(c) => options.command = Some(c.clone()),
+                None => return Err("-c: option requires an argument".to_string()),
+            },
+            "-l" | "--login" => options.login = true,
+            "--norc" => options.norc = true,
+            "--noprofile" => options.noprofile = true,
+            "--" => break,
+            a if a.starts_with('-') && a.len() > 1 => return Err(format!("{a}: invalid option")),
+            _ => {
+                options.operands.push(arg.clone());
+                break;
+            }
+        }
+    }
+    options.operands.extend(args.cloned());
+    Ok(options)
+}
 
 #[cfg(not(tarpaulin_include))]
 fn main() {
-    use crate::parsing::parser::init_calc_parser;
+    let args: Vec<String> = std::env::args().collect();
+    let options = match parse_options(args.get(1..).unwrap_or_default()) {
+        Ok(o) => Options {
+            login: o.login || args.first().is_some_and(|a| a.starts_with('-')),
+            ..o
+        },
+        Err(e) => {
+            eprintln!("tinysh: {e}");
+            eprintln!(
+                "usage: tinysh [-l] [--norc] [--noprofile] [-c command [name [args...]]] [script [args...]]"
+            );
+            std::process::exit(2);
+        }
+    };
+    let mut ctx = Context::new();
+    if options.login && !options.noprofile {
+        if let Some(profile) = profile_file(&ctx) {
+            load_startup_file(&profile, &mut ctx);
+        }
+    }
+    if let Some(command) = options.command {
+        if let Some((name, args)) = options.operands.split_first() {
+            ctx.script_name = Some(name.clone());
+            ctx.arguments = args.to_vec();
+        }
+        run_source(&command, &mut ctx);
+        std::process::exit(ctx.exit.unwrap_or(ctx.last_status));
+    }
+    // `tinysh script.sh args...`, also what a `#!/usr/bin/env tinysh` runs
+    if let Some(path) = options.operands.first() {
+        std::process::exit(run_file(path, &options.operands[1..], &mut ctx));
+    }
+    if !std::io::stdin().is_terminal() {
+        std::process::exit(run_lines(&mut *stdin(), &mut ctx));
+    }
+    // only the prompt reads the configuration, creating it on first start
+    ctx.config_file = Config::file();
+    if let Some(file) = &ctx.config_file {
+        match Config::load(file) {
+            Ok(config) => ctx.config = config,
+            Err(e) => eprintln!("tinysh: config: {e}"),
+        }
+    }
+    ctx.config.apply_path();
+    // before the rc file, which can change them
+    ctx.completion_specs = default_specs();
+    if !options.norc {
+        if let Some(rc) = rc_file(&ctx) {
+            load_startup_file(&rc, &mut ctx);
+        }
+    }
+    if let Some(code) = ctx.exit {
+        std::process::exit(code);
+    }
+    repl(&mut ctx);
+}
+
+/// Standard input without the buffering of `std::io::stdin`, which would
+/// swallow lines meant for the commands it starts.
+#[cfg(unix)]
+fn stdin() -> std::mem::ManuallyDrop<std::fs::File> {
+    use std::os::unix::io::FromRawFd;
+    std::mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(0) })
+}
+
+#[cfg(not(unix))]
+fn stdin() -> Box<std::io::Stdin> {
+    Box::new(std::io::stdin())
+}
 
+/// The prompt on the screen, for `transient_prompt` and the suggestions.
+struct Shown {
+    left: String,
+    right_width: usize,
+}
+
+/// Shows the primary or the continuation prompt with the right prompt.
+fn show_prompt<T: linefeed::Terminal>(
+    interface: &Interface<T>,
+    ctx: &Context,
+    continuation: bool,
+) -> Shown {
+    let left = if continuation {
+        prompt::continuation(ctx)
+    } else {
+        prompt::primary(ctx)
+    };
+    let right = prompt::right(ctx);
+    interface
+        .set_prompt(&prompt::with_right(&left, &right, prompt::terminal_width()))
+        .unwrap();
+    Shown {
+        left,
+        right_width: prompt::visible_width(&right),
+    }
+}
+
+/// Reads a line as `Interface::read_line` does, colouring it and showing
+/// what the history or completion suggest for it after each key. `before`
+/// holds the lines already read of the same command.
+fn read_line<T: linefeed::Terminal>(
+    interface: &Interface<T>,
+    ctx: &Context,
+    completer: &ShellCompleter,
+    suggestions: &Suggestions,
+    shown: &Shown,
+    before: &str,
+) -> ReadResult {
+    loop {
+        if let Some(result) = interface.read_line_step(None).unwrap() {
+            return result;
+        }
+        let line = interface.buffer();
+        let last_row = shown.left.rsplit('\n').next().unwrap_or_default();
+        let start = prompt::visible_width(last_row) + 1;
+        let column = start + prompt::visible_width(&line);
+        let limit = prompt::terminal_width().saturating_sub(shown.right_width);
+        // a line wrapped by the terminal is left as it is
+        if column <= limit {
+            let text = format!("{before}{line}");
+            let marks = highlights(&text, |name| completer.is_command(name));
+            highlight::draw(start, &paint(&line, &marks[before.len()..]));
+        }
+        let suggestion = suggest(&line, &ctx.history.entries, completer);
+        suggestions.show(&line, suggestion, column, limit);
+    }
+}
+
+/// Replaces the prompt of the line just entered by the transient prompt.
+fn collapse_prompt(ctx: &Context, shown: &str, line: &str) {
+    let rows = prompt::rows(shown, line, prompt::terminal_width());
+    let transient: String = prompt::transient(ctx)
+        .chars()
+        .filter(|c| !matches!(c, '\x01' | '\x02'))
+        .collect();
+    print!("\x1b[{rows}A\r\x1b[J{transient}{line}\n");
+}
+
+/// Adds a line to the history and to its file, unless `$HISTIGNORE` or a
+/// leading space keeps it out.
+fn record_history<T: linefeed::Terminal>(
+    interface: &Interface<T>,
+    ctx: &mut Context,
+    file: Option<&Path>,
+    line: &str,
+) {
+    let ignore = ctx.get_variable("HISTIGNORE").unwrap_or_default();
+    if !ctx.history.add(line, &ignore, ctx.config.history_size) {
+        return;
+    }
+    interface.add_history(line.to_string());
+    if let Some(path) = file {
+        if let Err(e) = ctx.history.save(path, ctx.config.history_size) {
+            eprintln!("tinysh: history: {}: {e}", path.display());
+        }
+    }
+}
+
+/// The tokens and the tree of a command.
+fn parse(buffer: &str) -> (Vec<SpannedToken>, Result<Ast, ParseError>) {
+    let data = Lexer {
+        str: buffer.to_string(),
+    }
+    .lex_spanned();
+    let parsed = init_calc_parser(buffer, &data).parse();
+    (data, parsed)
+}
+
+#[cfg(not(tarpaulin_include))]
+fn repl(ctx: &mut Context) {
     let interface = Interface::new("tinysh").unwrap();
-    let style = Color::Cyan;
-    let prompt_text = "tinysh> ";
-    let mut verbose = true;
+    let colour = |name: &str| parse_colour(name).unwrap_or(Color::Cyan);
+    let mut verbose = ctx.config.verbose;
+    let mut buffer = String::new();
 
     println!(
         "{}",
-        Color::Blue.paint("Welcome to tinysh 0.0.3 by Charlotte Thomas")
+        colour(&ctx.config.banner_colour).paint("Welcome to tinysh 0.0.3 by Charlotte Thomas")
     );
 
-    interface
-        .set_prompt(&format!(
-            "\x01{prefix}\x02{text}\x01{suffix}\x02",
-            prefix = style.prefix(),
-            text = prompt_text,
-            suffix = style.suffix()
-        ))
-        .unwrap();
+    interface.set_history_size(ctx.config.history_size);
+    let history = history_file(ctx);
+    if let Some(path) = &history {
+        if let Err(e) = ctx.history.load(path, ctx.config.history_size) {
+            eprintln!("tinysh: history: {}: {e}", path.display());
+        }
+    }
+    for entry in &ctx.history.entries {
+        interface.add_history(entry.clone());
+    }
+    let completer = Arc::new(ShellCompleter::new());
+    completer.update(ctx);
+    interface.set_completer(completer.clone());
+    let suggestions = Arc::new(Suggestions::new());
+    bind_keys(&interface, suggestions.clone());
+    let mut shown = show_prompt(&interface, ctx, false);
 
-    while let ReadResult::Input(line) = interface.read_line().unwrap() {
-        match line.as_str().trim() {
-            "exit" => break,
+    while let ReadResult::Input(line) =
+        read_line(&interface, ctx, &completer, &suggestions, &shown, &buffer)
+    {
+        if ctx.config.transient_prompt && buffer.is_empty() {
+            collapse_prompt(ctx, &shown.left, &line);
+        }
+        buffer += &line;
+        if let Some(stripped) = buffer.strip_suffix('\\') {
+            buffer = stripped.to_string();
+            shown = show_prompt(&interface, ctx, true);
+            continue;
+        }
+        let (mut data, mut parsed) = parse(&buffer);
+        if parsed.as_ref().is_err_and(|e| e.is_incomplete()) {
+            buffer += "\n";
+            shown = show_prompt(&interface, ctx, true);
+            continue;
+        }
+        // events are expanded once the whole command is read
+        match expand_history(&buffer, &ctx.history.entries) {
+            Ok(Some(expanded)) => {
+                println!("{expanded}");
+                buffer = expanded;
+                (data, parsed) = parse(&buffer);
+            }
+            Ok(None) => (),
+            Err(e) => {
+                eprintln!("tinysh: {e}");
+                buffer.clear();
+                shown = show_prompt(&interface, ctx, false);
+                continue;
+            }
+        }
+        // recorded before it runs, so that `history` lists it and
+        // `history -c` clears it
+        record_history(&interface, ctx, history.as_deref(), &buffer);
+        match buffer.as_str().trim() {
             "verbose" => {
                 verbose = !verbose;
                 println!(
@@ -43,18 +304,40 @@ fn main() {
             "info" => {
                 println!("{}",Color::Purple.paint(" Tinysh v0.0.3\n By Charlotte Thomas\n Repository: https://github.com/tinysh"))
             }
-            _ => {
-                let lexer = Lexer { str: line.clone() };
-                let data = lexer.lex();
-                let parser = &mut init_calc_parser(&data);
-                let ast = parser.parse();
-                println!("Lexing of line : {}", &line);
-                println!("{:?}", &data);
-                println!("Parsing");
-                println!("{:?}", &ast);
+            _ => match parsed {
+                Ok(ast) => {
+                    if verbose {
+                        println!("Lexing of line : {}", &buffer);
+                        println!("{:?}", &data);
+                        println!("Parsing");
+                        println!("{:?}", &ast);
+                    }
+                    let start = Instant::now();
+                    interpret(&ast, ctx);
+                    ctx.last_duration = start.elapsed();
+                }
+                Err(e) => report(&e, &buffer),
+            },
+        }
+        if ctx.history.take_edited() {
+            interface.clear_history();
+            for entry in &ctx.history.entries {
+                interface.add_history(entry.clone());
             }
         }
-        interface.add_history_unique(line);
+        buffer.clear();
+        if ctx.exit.is_some() {
+            break;
+        }
+        // rendered again each time for the escapes of `$PS1`, and so that
+        // `config set` and `config reload` take effect on the next prompt
+        interface.set_history_size(ctx.config.history_size);
+        completer.update(ctx);
+        shown = show_prompt(&interface, ctx, false);
     }
-    println!("{}", Color::Blue.paint("Exiting tinysh, goodbye :)"));
+    println!(
+        "{}",
+        colour(&ctx.config.banner_colour).paint("Exiting tinysh, goodbye :)")
+    );
+    std::process::exit(ctx.exit.unwrap_or(ctx.last_status));
 }
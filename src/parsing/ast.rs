@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use crate::lexing::token::Token;
+use crate::lexing::token::{CaseTerminator, Span, Token};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Parameters {
@@ -14,18 +14,188 @@ pub enum Parameters {
     RightRedirection,
     And,
     Or,
+    Semicolon,
+    Subshell,
+    Group,
+    Not,
     Null,
     Call(String, Vec<String>),
+    /// The words of one test of a `[[ ]]`
+    TestWords(Vec<TestWord>),
 }
 
+/// A word inside `[[ ]]`; one with a quoted part matches as it is rather
+/// than as a pattern.
 #[derive(Debug, Clone, PartialEq)]
+pub struct TestWord {
+    pub text: String,
+    pub quoted: bool,
+}
+
+#[derive(Debug, Clone)]
 pub enum Ast {
     Nil,
     Node {
         value: Parameters,
         left: Box<Ast>,
         right: Box<Ast>,
+        span: Span,
+    },
+    /// `if condition; then ...; else ...; fi`, an `elif` is an `If` in the
+    /// else branch.
+    If {
+        condition: Box<Ast>,
+        then_branch: Box<Ast>,
+        else_branch: Box<Ast>,
+        span: Span,
+    },
+    /// `while` loop, or `until` loop when `until` is set
+    While {
+        condition: Box<Ast>,
+        body: Box<Ast>,
+        until: bool,
+        span: Span,
+    },
+    /// `for variable in words; do ...; done`, `words` is `None` without `in`
+    For {
+        variable: String,
+        words: Option<Vec<String>>,
+        body: Box<Ast>,
+        span: Span,
+    },
+    /// `for ((init; condition; update)); do ...; done`
+    ArithmeticFor {
+        init: String,
+        condition: String,
+        update: String,
+        body: Box<Ast>,
+        span: Span,
     },
+    /// `case word in pattern|pattern) ...;; esac`
+    Case {
+        word: String,
+        arms: Vec<CaseArm>,
+        span: Span,
+    },
+    /// `name() body` or `function name body`
+    Function {
+        name: String,
+        body: Box<Ast>,
+        span: Span,
+    },
+    /// `[[ expression ]]`, whose leaves are the words of each test
+    Test {
+        expression: Box<Ast>,
+        span: Span,
+    },
+}
+
+/// One `pattern|pattern) list;;` of a `case`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseArm {
+    pub patterns: Vec<String>,
+    pub body: Ast,
+    pub terminator: CaseTerminator,
+}
+
+/// Spans are only positional metadata: two trees with the same shape are
+/// equal wherever they were parsed from.
+impl PartialEq for Ast {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Ast::Nil, Ast::Nil) => true,
+            (
+                Ast::Node {
+                    value: v1,
+                    left: l1,
+                    right: r1,
+                    ..
+                },
+                Ast::Node {
+                    value: v2,
+                    left: l2,
+                    right: r2,
+                    ..
+                },
+            ) => v1 == v2 && l1 == l2 && r1 == r2,
+            (
+                Ast::If {
+                    condition: c1,
+                    then_branch: t1,
+                    else_branch: e1,
+                    ..
+                },
+                Ast::If {
+                    condition: c2,
+                    then_branch: t2,
+                    else_branch: e2,
+                    ..
+                },
+            ) => c1 == c2 && t1 == t2 && e1 == e2,
+            (
+                Ast::While {
+                    condition: c1,
+                    body: b1,
+                    until: u1,
+                    ..
+                },
+                Ast::While {
+                    condition: c2,
+                    body: b2,
+                    until: u2,
+                    ..
+                },
+            ) => c1 == c2 && b1 == b2 && u1 == u2,
+            (
+                Ast::For {
+                    variable: v1,
+                    words: w1,
+                    body: b1,
+                    ..
+                },
+                Ast::For {
+                    variable: v2,
+                    words: w2,
+                    body: b2,
+                    ..
+                },
+            ) => v1 == v2 && w1 == w2 && b1 == b2,
+            (
+                Ast::ArithmeticFor {
+                    init: i1,
+                    condition: c1,
+                    update: u1,
+                    body: b1,
+                    ..
+                },
+                Ast::ArithmeticFor {
+                    init: i2,
+                    condition: c2,
+                    update: u2,
+                    body: b2,
+                    ..
+                },
+            ) => i1 == i2 && c1 == c2 && u1 == u2 && b1 == b2,
+            (
+                Ast::Case {
+                    word: w1, arms: a1, ..
+                },
+                Ast::Case {
+                    word: w2, arms: a2, ..
+                },
+            ) => w1 == w2 && a1 == a2,
+            (
+                Ast::Function {
+                    name: n1, body: b1, ..
+                },
+                Ast::Function {
+                    name: n2, body: b2, ..
+                },
+            ) => n1 == n2 && b1 == b2,
+            (Ast::Test { expression: e1, .. }, Ast::Test { expression: e2, .. }) => e1 == e2,
+            _ => false,
+        }
+    }
 }
 
 impl Display for Parameters {
@@ -41,8 +211,16 @@ impl Display for Parameters {
             Parameters::RightRedirection => write!(f, "<"),
             Parameters::And => write!(f, "&&"),
             Parameters::Or => write!(f, "or"),
+            Parameters::Semicolon => write!(f, ";"),
+            Parameters::Subshell => write!(f, "()"),
+            Parameters::Group => write!(f, "{{}}"),
+            Parameters::Not => write!(f, "!"),
             Parameters::Null => write!(f, ""),
             Parameters::Call(s, l) => write!(f, "{} {}", s, l.join(" ")),
+            Parameters::TestWords(words) => {
+                let words: Vec<&str> = words.iter().map(|w| w.text.as_str()).collect();
+                write!(f, "{}", words.join(" "))
+            }
         }
     }
 }
@@ -51,9 +229,71 @@ impl Display for Ast {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Ast::Nil => write!(f, ""),
-            Ast::Node { value, left, right } => {
+            Ast::Node {
+                value: Parameters::Subshell,
+                left,
+                ..
+            } => write!(f, "({left})"),
+            Ast::Node {
+                value: Parameters::Group,
+                left,
+                ..
+            } => write!(f, "{{ {left}; }}"),
+            Ast::Node {
+                value: Parameters::Not,
+                left,
+                ..
+            } => write!(f, "! {left}"),
+            // a leaf keeps the spaces of a quoted word
+            Ast::Node {
+                value, left, right, ..
+            } if **left == Ast::Nil && **right == Ast::Nil => write!(f, "{value}"),
+            Ast::Node {
+                value, left, right, ..
+            } => {
                 write!(f, "{}", format!("{} {} {}", left, value, right).trim())
             }
+            Ast::If { .. } => write!(f, "{} fi", self.if_chain()),
+            Ast::While {
+                condition,
+                body,
+                until,
+                ..
+            } => {
+                let keyword = if *until { "until" } else { "while" };
+                write!(f, "{keyword} {condition}; do {body}; done")
+            }
+            Ast::For {
+                variable,
+                words,
+                body,
+                ..
+            } => match words {
+                None => write!(f, "for {variable}; do {body}; done"),
+                Some(w) => write!(f, "for {variable} in {}; do {body}; done", w.join(" ")),
+            },
+            Ast::ArithmeticFor {
+                init,
+                condition,
+                update,
+                body,
+                ..
+            } => write!(f, "for (({init}; {condition}; {update})); do {body}; done"),
+            Ast::Case { word, arms, .. } => {
+                write!(f, "case {word} in")?;
+                for arm in arms {
+                    write!(
+                        f,
+                        " {}) {}{}",
+                        arm.patterns.join("|"),
+                        arm.body,
+                        arm.terminator
+                    )?;
+                }
+                write!(f, " esac")
+            }
+            Ast::Function { name, body, .. } => write!(f, "{name}() {body}"),
+            Ast::Test { expression, .. } => write!(f, "[[ {expression} ]]"),
         }
     }
 }
@@ -62,11 +302,12 @@ pub fn token_to_parameter(token: Token) -> Parameters {
     match token {
         Token::Or => Parameters::Or,
         Token::And => Parameters::And,
+        Token::Semicolon => Parameters::Semicolon,
         Token::Pipe => Parameters::Pipe,
-        Token::Int(s) => Parameters::Int(s.clone()),
-        Token::Float(s) => Parameters::Float(s.clone()),
-        Token::Identifier(s) => Parameters::Identifier(s.clone()),
-        Token::Bool(b) => Parameters::Bool(b.clone()),
+        Token::Int(s) => Parameters::Int(s),
+        Token::Float(s) => Parameters::Float(s),
+        Token::Identifier(s) => Parameters::Identifier(s),
+        Token::Bool(b) => Parameters::Bool(b),
         Token::LeftRedirection => Parameters::LeftRedirection,
         Token::RightRedirection => Parameters::RightRedirection,
         _ => Parameters::Null,
@@ -75,23 +316,62 @@ pub fn token_to_parameter(token: Token) -> Parameters {
 
 impl Ast {
     pub fn new(p: Parameters) -> Ast {
+        Ast::new_spanned(p, Span::default())
+    }
+
+    pub fn new_spanned(p: Parameters, span: Span) -> Ast {
         Ast::Node {
             value: p,
             left: Box::from(Ast::Nil),
             right: Box::from(Ast::Nil),
+            span,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Ast::Nil => Span::default(),
+            Ast::Node { span, .. }
+            | Ast::If { span, .. }
+            | Ast::While { span, .. }
+            | Ast::For { span, .. }
+            | Ast::ArithmeticFor { span, .. }
+            | Ast::Case { span, .. }
+            | Ast::Function { span, .. }
+            | Ast::Test { span, .. } => *span,
+        }
+    }
+
+    /// `if ...; then ...;` followed by the `elif`/`else` parts, without `fi`.
+    fn if_chain(&self) -> String {
+        let Ast::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } = self
+        else {
+            return self.to_string();
+        };
+        let mut str = format!("if {condition}; then {then_branch};");
+        match &**else_branch {
+            Ast::Nil => (),
+            e @ Ast::If { .. } => str += &format!(" el{}", e.if_chain()),
+            e => str += &format!(" else {e};"),
         }
+        str
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::lexing::token::Token;
+    use crate::lexing::token::{CaseTerminator, Span, Token};
 
-    use super::{token_to_parameter, Ast, Parameters};
+    use super::{token_to_parameter, Ast, CaseArm, Parameters};
 
     #[test]
     fn test_display_parameters() {
-        let expected = "[5, 5.5, test, test2, false, |, >, <, &&, or, ls color, ]";
+        let expected = "[5, 5.5, test, test2, false, |, >, <, &&, or, ;, (), {}, ls color, ]";
         let value = vec![
             Parameters::Int(5),
             Parameters::Float(5.5),
@@ -103,6 +383,9 @@ mod test {
             Parameters::RightRedirection,
             Parameters::And,
             Parameters::Or,
+            Parameters::Semicolon,
+            Parameters::Subshell,
+            Parameters::Group,
             Parameters::Call("ls".to_string(), vec!["color".to_string()]),
             Parameters::Null,
         ];
@@ -131,6 +414,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::redundant_closure)]
     fn test_token_to_parameter() {
         let expected = vec![
             Parameters::Identifier("t".to_string()),
@@ -142,6 +426,7 @@ mod test {
             Parameters::LeftRedirection,
             Parameters::RightRedirection,
             Parameters::Bool(false),
+            Parameters::Semicolon,
             Parameters::Null,
         ];
         let v = vec![
@@ -154,6 +439,7 @@ mod test {
             Token::LeftRedirection,
             Token::RightRedirection,
             Token::Bool(false),
+            Token::Semicolon,
             Token::PreAnd,
         ];
         let mut value = Vec::new();
@@ -162,4 +448,111 @@ mod test {
             .for_each(|v| value.push(v));
         assert_eq!(value, expected);
     }
+
+    #[test]
+    fn test_display_subshell() {
+        let v = Ast::Node {
+            value: Parameters::Subshell,
+            left: Box::from(Ast::new(Parameters::Identifier("ls".to_string()))),
+            right: Box::from(Ast::Nil),
+            span: Span::default(),
+        };
+        assert_eq!(format!("{v}"), "(ls)");
+        let v = Ast::Node {
+            value: Parameters::Group,
+            left: Box::from(Ast::new(Parameters::Identifier("ls".to_string()))),
+            right: Box::from(Ast::Nil),
+            span: Span::default(),
+        };
+        assert_eq!(format!("{v}"), "{ ls; }");
+    }
+
+    #[test]
+    fn test_display_if() {
+        let v = Ast::If {
+            condition: Box::from(Ast::new(Parameters::Identifier("a".to_string()))),
+            then_branch: Box::from(Ast::new(Parameters::Identifier("b".to_string()))),
+            else_branch: Box::from(Ast::If {
+                condition: Box::from(Ast::new(Parameters::Identifier("c".to_string()))),
+                then_branch: Box::from(Ast::new(Parameters::Identifier("d".to_string()))),
+                else_branch: Box::from(Ast::new(Parameters::Identifier("e".to_string()))),
+                span: Span::default(),
+            }),
+            span: Span::new(0, 5, 1, 1),
+        };
+        assert_eq!(format!("{v}"), "if a; then b; elif c; then d; else e; fi");
+        assert_eq!(v.span(), Span::new(0, 5, 1, 1));
+    }
+
+    #[test]
+    fn test_display_loops() {
+        let body = Box::from(Ast::new(Parameters::Identifier("b".to_string())));
+        let v = Ast::While {
+            condition: Box::from(Ast::new(Parameters::Identifier("a".to_string()))),
+            body: body.clone(),
+            until: true,
+            span: Span::default(),
+        };
+        assert_eq!(format!("{v}"), "until a; do b; done");
+        let v = Ast::For {
+            variable: "x".to_string(),
+            words: Some(vec!["1".to_string(), "2".to_string()]),
+            body: body.clone(),
+            span: Span::default(),
+        };
+        assert_eq!(format!("{v}"), "for x in 1 2; do b; done");
+        let v = Ast::ArithmeticFor {
+            init: "i=0".to_string(),
+            condition: "i<3".to_string(),
+            update: "i++".to_string(),
+            body,
+            span: Span::default(),
+        };
+        assert_eq!(format!("{v}"), "for ((i=0; i<3; i++)); do b; done");
+    }
+
+    #[test]
+    fn test_display_case() {
+        let v = Ast::Case {
+            word: "$x".to_string(),
+            arms: vec![
+                CaseArm {
+                    patterns: vec!["a".to_string(), "b*".to_string()],
+                    body: Ast::new(Parameters::Identifier("c".to_string())),
+                    terminator: CaseTerminator::FallThrough,
+                },
+                CaseArm {
+                    patterns: vec!["*".to_string()],
+                    body: Ast::Nil,
+                    terminator: CaseTerminator::Break,
+                },
+            ],
+            span: Span::default(),
+        };
+        assert_eq!(format!("{v}"), "case $x in a|b*) c;& *) ;; esac");
+    }
+
+    #[test]
+    fn test_display_function() {
+        let v = Ast::Function {
+            name: "f".to_string(),
+            body: Box::from(Ast::Node {
+                value: Parameters::Group,
+                left: Box::from(Ast::new(Parameters::Identifier("a".to_string()))),
+                right: Box::from(Ast::Nil),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        assert_eq!(format!("{v}"), "f() { a; }");
+    }
+
+    #[test]
+    fn test_eq_ignores_span() {
+        let a = Ast::new_spanned(Parameters::Int(5), Span::new(0, 1, 1, 1));
+        let b = Ast::new(Parameters::Int(5));
+        assert_eq!(a, b);
+        assert_eq!(a.span(), Span::new(0, 1, 1, 1));
+        assert_eq!(Ast::Nil.span(), Span::default());
+    }
 }
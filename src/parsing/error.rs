@@ -0,0 +1,194 @@
+use std::fmt::Display;
+
+use ansi_term::{Color, Style};
+
+use crate::lexing::token::{Span, TokenType};
+
+/// Secondary location attached to an error, e.g. where an unclosed
+/// delimiter was opened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hint {
+    pub message: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub expected: Vec<TokenType>,
+    pub found: TokenType,
+    pub span: Span,
+    pub hint: Option<Hint>,
+}
+
+impl ParseError {
+    pub fn new(expected: Vec<TokenType>, found: TokenType, span: Span) -> ParseError {
+        ParseError {
+            expected,
+            found,
+            span,
+            hint: None,
+        }
+    }
+
+    pub fn unexpected(found: TokenType, span: Span) -> ParseError {
+        ParseError::new(vec![], found, span)
+    }
+
+    pub fn with_hint(mut self, message: &str, span: Span) -> ParseError {
+        self.hint = Some(Hint {
+            message: message.to_string(),
+            span,
+        });
+        self
+    }
+
+    /// Whether the input merely stopped early (unclosed quote or group,
+    /// dangling operator) and more lines could complete it.
+    pub fn is_incomplete(&self) -> bool {
+        self.found == TokenType::Null
+    }
+
+    /// Renders the error against the `source` it was parsed from: the message,
+    /// then the offending line with the span underlined, then the hint if any.
+    pub fn render(&self, source: &str) -> String {
+        self.render_with(source, true)
+    }
+
+    /// Same as `render` without colours, for output that is not a terminal.
+    pub fn render_plain(&self, source: &str) -> String {
+        self.render_with(source, false)
+    }
+
+    fn render_with(&self, source: &str, colours: bool) -> String {
+        let mut out = format!(
+            "{} {}\n",
+            paint(Color::Red.bold(), "error:", colours),
+            paint(Color::White.bold(), &self.to_string(), colours)
+        );
+        let gutter = self
+            .span
+            .line
+            .max(self.hint.as_ref().map_or(0, |h| h.span.line))
+            .to_string()
+            .len();
+        out += &format!(
+            "{} {}\n",
+            " ".repeat(gutter),
+            paint(Color::Blue.normal(), "|", colours)
+        );
+        out += &underline(source, self.span, gutter, '^', Color::Red, "", colours);
+        if let Some(hint) = &self.hint {
+            out += &underline(
+                source,
+                hint.span,
+                gutter,
+                '-',
+                Color::Blue,
+                &hint.message,
+                colours,
+            );
+        }
+        out
+    }
+}
+
+fn paint(style: Style, text: &str, colours: bool) -> String {
+    if colours {
+        style.paint(text).to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+fn underline(
+    source: &str,
+    span: Span,
+    gutter: usize,
+    mark: char,
+    color: Color,
+    label: &str,
+    colours: bool,
+) -> String {
+    let text = source
+        .lines()
+        .nth(span.line.saturating_sub(1))
+        .unwrap_or("");
+    let line_end = source[span.start.min(source.len())..]
+        .find('\n')
+        .map_or(source.len(), |i| span.start + i);
+    let width = source
+        .get(span.start..span.end.min(line_end))
+        .map_or(0, |s| s.chars().count())
+        .max(1);
+    let pipe = paint(Color::Blue.normal(), "|", colours);
+    format!(
+        "{} {pipe} {text}\n{} {pipe} {}{} {}\n",
+        paint(
+            Color::Blue.normal(),
+            &format!("{:>gutter$}", span.line),
+            colours
+        ),
+        " ".repeat(gutter),
+        " ".repeat(span.column.saturating_sub(1)),
+        paint(color.bold(), &mark.to_string().repeat(width), colours),
+        paint(color.bold(), label, colours),
+    )
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.expected.is_empty() {
+            return write!(f, "unexpected {}", self.found);
+        }
+        let expected: Vec<String> = self.expected.iter().map(|t| t.to_string()).collect();
+        write!(
+            f,
+            "expected {}, found {}",
+            expected.join(" or "),
+            self.found
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lexing::token::{Span, TokenType};
+
+    use super::ParseError;
+
+    #[test]
+    fn test_display() {
+        let value = ParseError::new(vec![TokenType::RPar], TokenType::Null, Span::default());
+        assert_eq!(value.to_string(), "expected `)`, found end of input");
+        let value = ParseError::unexpected(TokenType::RPar, Span::default());
+        assert_eq!(value.to_string(), "unexpected `)`");
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        let value = ParseError::new(vec![TokenType::RPar], TokenType::Null, Span::default());
+        assert!(value.is_incomplete());
+        let value = ParseError::unexpected(TokenType::RPar, Span::default());
+        assert!(!value.is_incomplete());
+    }
+
+    #[test]
+    fn test_render() {
+        let value = ParseError::new(
+            vec![TokenType::RPar],
+            TokenType::Null,
+            Span::new(5, 5, 1, 6),
+        )
+        .with_hint("unclosed `(` opened here", Span::new(0, 1, 1, 1));
+        let rendered = value.render("(a | b");
+        assert!(rendered.contains("expected `)`, found end of input"));
+        assert!(rendered.contains("(a | b"));
+        assert!(rendered.contains("     \u{1b}[1;31m^"));
+        assert!(rendered.contains("unclosed `(` opened here"));
+        let plain = value.render_plain("(a | b");
+        assert!(!plain.contains('\u{1b}'));
+        assert!(plain.starts_with(
+            "error: expected `)`, found end of input\n  |\n1 | (a | b\n  |      ^ \n"
+        ));
+    }
+}
@@ -1,17 +1,23 @@
 use crate::{
-    lexing::token::Token,
+    lexing::token::{SpannedToken, Token, TokenType},
     parsing::{
         ast::{token_to_parameter, Ast},
+        error::ParseError,
+        parselets::prefix_parselet::parse_word,
         parser::Parser,
     },
 };
 
 pub trait InfixParselet {
-    fn parse(&self, parser: &mut Parser, left: &Ast, token: Token) -> Ast;
+    fn parse(
+        &self,
+        parser: &mut Parser,
+        left: &Ast,
+        token: SpannedToken,
+    ) -> Result<Ast, ParseError>;
     fn get_precedence(&self) -> i64;
 }
 
-
 #[cfg(not(tarpaulin_include))]
 pub struct NullParset {}
 
@@ -20,10 +26,20 @@ pub struct OperatorInfixParselet {
     pub precedence: i64,
 }
 
+/// `>` and `<` take exactly one word on their right: the file.
+pub struct RedirectionInfixParselet {
+    pub precedence: i64,
+}
+
 #[cfg(not(tarpaulin_include))]
 impl InfixParselet for NullParset {
-    fn parse(&self, _parser: &mut Parser, left: &Ast, _token: Token) -> Ast {
-        left.clone()
+    fn parse(
+        &self,
+        _parser: &mut Parser,
+        left: &Ast,
+        _token: SpannedToken,
+    ) -> Result<Ast, ParseError> {
+        Ok(left.clone())
     }
 
     fn get_precedence(&self) -> i64 {
@@ -32,18 +48,68 @@ impl InfixParselet for NullParset {
 }
 
 impl InfixParselet for OperatorInfixParselet {
-    fn parse(&self, parser: &mut Parser, left: &Ast, token: Token) -> Ast {
+    fn parse(
+        &self,
+        parser: &mut Parser,
+        left: &Ast,
+        token: SpannedToken,
+    ) -> Result<Ast, ParseError> {
         let right = parser.parse_expression(if self.is_right {
             self.get_precedence() - 1
         } else {
             self.get_precedence()
-        });
-        let param = token_to_parameter(token);
-        Ast::Node {
+        })?;
+        // A list operator at the very end of the input still needs a
+        // right-hand command, which a continuation line can provide.
+        if right == Ast::Nil && matches!(token.token, Token::Pipe | Token::And | Token::Or) {
+            let next = parser.look_ahead_span();
+            return Err(ParseError::new(
+                vec![TokenType::Identifier],
+                parser.look_ahead_type(),
+                next,
+            ));
+        }
+        // `a;` is just `a`
+        if right == Ast::Nil && token.token == Token::Semicolon {
+            return Ok(left.clone());
+        }
+        let span = left.span().merge(token.span).merge(right.span());
+        let param = token_to_parameter(token.token);
+        Ok(Ast::Node {
             value: param,
             left: Box::from(left.clone()),
             right: Box::from(right),
+            span,
+        })
+    }
+    fn get_precedence(&self) -> i64 {
+        self.precedence
+    }
+}
+
+impl InfixParselet for RedirectionInfixParselet {
+    fn parse(
+        &self,
+        parser: &mut Parser,
+        left: &Ast,
+        token: SpannedToken,
+    ) -> Result<Ast, ParseError> {
+        if !parser.match_word() {
+            return Err(ParseError::new(
+                vec![TokenType::Identifier],
+                parser.look_ahead_type(),
+                parser.look_ahead_span(),
+            ));
         }
+        let next = parser.consume();
+        let right = parse_word(parser, next)?;
+        let span = left.span().merge(token.span).merge(right.span());
+        Ok(Ast::Node {
+            value: token_to_parameter(token.token),
+            left: Box::from(left.clone()),
+            right: Box::from(right),
+            span,
+        })
     }
     fn get_precedence(&self) -> i64 {
         self.precedence
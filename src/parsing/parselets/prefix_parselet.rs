@@ -1,13 +1,14 @@
 use crate::{
-    lexing::token::{Token, TokenType},
+    lexing::token::{CaseTerminator, Keyword, Span, SpannedToken, Token, TokenType},
     parsing::{
-        ast::{token_to_parameter, Ast},
+        ast::{token_to_parameter, Ast, CaseArm, Parameters, TestWord},
+        error::ParseError,
         parser::Parser,
     },
 };
 
 pub trait PrefixParselet {
-    fn parse(&self, parser: &mut Parser, token: Token) -> Ast;
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError>;
 }
 
 pub struct NullParselet {}
@@ -18,62 +19,614 @@ pub struct OperatorPrefixParselet {}
 
 pub struct GroupParselet {}
 
+pub struct BraceGroupParselet {}
+
 pub struct QuoteParselet {}
 
+pub struct CommandParselet {}
+
+pub struct IfParselet {}
+
+pub struct WhileParselet {
+    pub until: bool,
+}
+
+pub struct ForParselet {}
+
+pub struct CaseParselet {}
+
+pub struct FunctionParselet {}
+
+pub struct TestParselet {}
+
+pub struct TestWordsParselet {}
+
+pub struct NotParselet {}
+
+/// The value of `token`, unless it would not show as written: `1.50` and
+/// `007` are words rather than numbers. An identifier keeps the text the
+/// lexer gave it, without the backslash of `\ `.
+fn written_value(parser: &Parser, token: &SpannedToken) -> Parameters {
+    let text = parser.text(token.span.start, token.span.end);
+    match token_to_parameter(token.token.clone()) {
+        value @ Parameters::Identifier(_) => value,
+        value if value.to_string() == text => value,
+        _ => Parameters::Identifier(text.to_string()),
+    }
+}
+
+/// Parses one shell word starting at `token`, as written in the source.
+/// Tokens written without whitespace between them (`-la`, `~/bin`, `"a"b`)
+/// are joined into a single `Identifier`; a lone token keeps its own value.
+pub fn parse_word(parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+    Ok(parse_quoted_word(parser, token)?.0)
+}
+
+/// `parse_word`, also telling whether a part of the word was quoted.
+fn parse_quoted_word(parser: &mut Parser, token: SpannedToken) -> Result<(Ast, bool), ParseError> {
+    let mut quoted = token.token == Token::Quote;
+    let mut word = match token.token {
+        Token::Quote => QuoteParselet {}.parse(parser, token)?,
+        _ => Ast::new_spanned(written_value(parser, &token), token.span),
+    };
+    let mut text = word.to_string();
+    let mut span = word.span();
+    while parser.match_word() && parser.look_ahead_span().start == span.end {
+        let next = parser.consume();
+        quoted |= next.token == Token::Quote;
+        let next = match next.token {
+            Token::Quote => QuoteParselet {}.parse(parser, next)?,
+            Token::Identifier(text) => Ast::new_spanned(Parameters::Identifier(text), next.span),
+            _ => Ast::new_spanned(
+                Parameters::Identifier(parser.text(next.span.start, next.span.end).to_string()),
+                next.span,
+            ),
+        };
+        text += &next.to_string();
+        span = span.merge(next.span());
+        word = Ast::new_spanned(Parameters::Identifier(text.clone()), span);
+    }
+    Ok((word, quoted))
+}
+
 impl PrefixParselet for ValueParselet {
-    fn parse(&self, _parser: &mut Parser, token: Token) -> Ast {
-        Ast::Node {
-            value: token_to_parameter(token),
-            left: Box::from(Ast::Nil),
-            right: Box::from(Ast::Nil),
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        // a number written as `1.50` names a command
+        match written_value(parser, &token) {
+            Parameters::Identifier(_) => CommandParselet {}.parse(parser, token),
+            value => Ok(Ast::new_spanned(value, token.span)),
         }
     }
 }
 
 impl PrefixParselet for NullParselet {
-    fn parse(&self, _parser: &mut Parser, _token: Token) -> Ast {
-        Ast::Nil
+    fn parse(&self, _parser: &mut Parser, _token: SpannedToken) -> Result<Ast, ParseError> {
+        Ok(Ast::Nil)
     }
 }
 
 impl PrefixParselet for OperatorPrefixParselet {
-    fn parse(&self, parser: &mut Parser, token: Token) -> Ast {
-        let operand = parser.parse_expression_empty();
-        Ast::Node {
-            value: token_to_parameter(token),
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        let operand = parser.parse_expression_empty()?;
+        let span = token.span.merge(operand.span());
+        Ok(Ast::Node {
+            value: token_to_parameter(token.token),
             left: Box::from(operand),
             right: Box::from(Ast::Nil),
-        }
+            span,
+        })
     }
 }
 
 impl PrefixParselet for GroupParselet {
-    fn parse(&self, parser: &mut Parser, _token: Token) -> Ast {
-        let expression = parser.parse_expression_empty();
-        parser.consume_expected(crate::lexing::token::TokenType::RPar);
-        expression
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        let expression = parser.parse_expression_empty()?;
+        let closing = parser
+            .consume_expected(TokenType::RPar)
+            .map_err(|e| e.with_hint("unclosed `(` opened here", token.span))?;
+        Ok(Ast::Node {
+            value: Parameters::Subshell,
+            left: Box::from(expression),
+            right: Box::from(Ast::Nil),
+            span: token.span.merge(closing.span),
+        })
     }
 }
 
-impl PrefixParselet for QuoteParselet {
-    fn parse(&self, parser: &mut Parser, _token: Token) -> Ast {
-        let mut str: String = String::new();
+impl PrefixParselet for BraceGroupParselet {
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        let expression = parser.parse_expression_empty()?;
+        let closing = parser
+            .consume_expected(TokenType::RBrace)
+            .map_err(|e| e.with_hint("unclosed `{` opened here", token.span))?;
+        Ok(Ast::Node {
+            value: Parameters::Group,
+            left: Box::from(expression),
+            right: Box::from(Ast::Nil),
+            span: token.span.merge(closing.span),
+        })
+    }
+}
+
+/// Parses what follows an `if` or `elif` up to, but not including, `fi`.
+fn parse_if_chain(parser: &mut Parser, token: &SpannedToken) -> Result<Ast, ParseError> {
+    let hint = format!("unclosed `{}` opened here", token.token);
+    let condition = parser.parse_expression_empty()?;
+    parser
+        .consume_expected(TokenType::Keyword(Keyword::Then))
+        .map_err(|e| e.with_hint(&hint, token.span))?;
+    let then_branch = parser.parse_expression_empty()?;
+    let else_branch = if parser.match_token(TokenType::Keyword(Keyword::Elif)) {
+        let elif = parser.consume();
+        parse_if_chain(parser, &elif)?
+    } else if parser.match_token(TokenType::Keyword(Keyword::Else)) {
+        parser.consume();
+        parser.parse_expression_empty()?
+    } else {
+        Ast::Nil
+    };
+    let span = token
+        .span
+        .merge(then_branch.span())
+        .merge(else_branch.span());
+    Ok(Ast::If {
+        condition: Box::from(condition),
+        then_branch: Box::from(then_branch),
+        else_branch: Box::from(else_branch),
+        span,
+    })
+}
+
+impl PrefixParselet for IfParselet {
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        let ast = parse_if_chain(parser, &token)?;
+        let closing = parser
+            .consume_expected(TokenType::Keyword(Keyword::Fi))
+            .map_err(|e| e.with_hint("unclosed `if` opened here", token.span))?;
+        match ast {
+            Ast::If {
+                condition,
+                then_branch,
+                else_branch,
+                span,
+            } => Ok(Ast::If {
+                condition,
+                then_branch,
+                else_branch,
+                span: span.merge(closing.span),
+            }),
+            ast => Ok(ast),
+        }
+    }
+}
+
+/// Parses `do list done`, returning the list and the span up to `done`.
+fn parse_do_group(parser: &mut Parser, token: &SpannedToken) -> Result<(Ast, Span), ParseError> {
+    let hint = format!("unclosed `{}` opened here", token.token);
+    parser
+        .consume_expected(TokenType::Keyword(Keyword::Do))
+        .map_err(|e| e.with_hint(&hint, token.span))?;
+    let body = parser.parse_expression_empty()?;
+    let closing = parser
+        .consume_expected(TokenType::Keyword(Keyword::Done))
+        .map_err(|e| e.with_hint(&hint, token.span))?;
+    Ok((body, token.span.merge(closing.span)))
+}
+
+/// Reads the raw text of `((init; condition; update))` after the opening
+/// `((`, splitting it on the top level `;`.
+fn parse_arithmetic_header(
+    parser: &mut Parser,
+    token: &SpannedToken,
+) -> Result<Vec<String>, ParseError> {
+    let mut parts = vec![String::new()];
+    let mut depth = 0;
+    let mut last_end = 0;
+    loop {
+        let t = parser.consume();
+        let text = match t.token {
+            Token::Null => {
+                return Err(
+                    ParseError::new(vec![TokenType::RPar], TokenType::Null, t.span)
+                        .with_hint("unclosed `((` opened here", token.span),
+                )
+            }
+            Token::RPar if depth == 0 => {
+                parser
+                    .consume_expected(TokenType::RPar)
+                    .map_err(|e| e.with_hint("unclosed `((` opened here", token.span))?;
+                break;
+            }
+            Token::Semicolon if depth == 0 => {
+                parts.push(String::new());
+                continue;
+            }
+            Token::LPar => {
+                depth += 1;
+                "(".to_string()
+            }
+            Token::RPar => {
+                depth -= 1;
+                ")".to_string()
+            }
+            Token::And => "&&".to_string(),
+            Token::Or => "||".to_string(),
+            t => t.to_string(),
+        };
+        let part = parts.last_mut().unwrap();
+        if !part.is_empty() && t.span.start > last_end {
+            part.push(' ');
+        }
+        part.push_str(&text);
+        last_end = t.span.end;
+    }
+    if parts.len() != 3 {
+        return Err(ParseError::new(
+            vec![TokenType::Semicolon],
+            TokenType::RPar,
+            parser.look_ahead_span(),
+        )
+        .with_hint(
+            "`for ((init; condition; update))` needs three parts",
+            token.span,
+        ));
+    }
+    Ok(parts)
+}
 
-        if !parser.match_token(TokenType::Quote) {
-            while !parser.match_token(TokenType::Quote) {
-                match parser.consume() {
-                    Token::Identifier(s) => str = str + &s.to_string(),
+impl PrefixParselet for WhileParselet {
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        let condition = parser.parse_expression_empty()?;
+        let (body, span) = parse_do_group(parser, &token)?;
+        Ok(Ast::While {
+            condition: Box::from(condition),
+            body: Box::from(body),
+            until: self.until,
+            span,
+        })
+    }
+}
 
-                    t => str = str + &t.to_string(),
+impl PrefixParselet for ForParselet {
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        if parser.match_token(TokenType::LPar) {
+            parser.consume();
+            parser.consume_expected(TokenType::LPar)?;
+            let mut parts = parse_arithmetic_header(parser, &token)?;
+            if parser.match_token(TokenType::Semicolon) {
+                parser.consume();
+            }
+            let (body, span) = parse_do_group(parser, &token)?;
+            let update = parts.pop().unwrap_or_default();
+            let condition = parts.pop().unwrap_or_default();
+            let init = parts.pop().unwrap_or_default();
+            return Ok(Ast::ArithmeticFor {
+                init: init.trim().to_string(),
+                condition: condition.trim().to_string(),
+                update: update.trim().to_string(),
+                body: Box::from(body),
+                span,
+            });
+        }
+        let variable = match parser.consume_expected(TokenType::Identifier)?.token {
+            Token::Identifier(s) => s,
+            t => t.to_string(),
+        };
+        let words = if parser.match_token(TokenType::Keyword(Keyword::In)) {
+            parser.consume();
+            let mut words = Vec::new();
+            while parser.match_word() {
+                let next = parser.consume();
+                words.push(parse_word(parser, next)?.to_string());
+            }
+            Some(words)
+        } else {
+            None
+        };
+        if parser.match_token(TokenType::Semicolon) {
+            parser.consume();
+        }
+        let (body, span) = parse_do_group(parser, &token)?;
+        Ok(Ast::For {
+            variable,
+            words,
+            body: Box::from(body),
+            span,
+        })
+    }
+}
+
+impl PrefixParselet for CaseParselet {
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        let hint = "unclosed `case` opened here";
+        if !parser.match_word() {
+            return Err(ParseError::new(
+                vec![TokenType::Identifier],
+                parser.look_ahead_type(),
+                parser.look_ahead_span(),
+            ));
+        }
+        let next = parser.consume();
+        let word = parse_word(parser, next)?.to_string();
+        parser
+            .consume_expected(TokenType::Keyword(Keyword::In))
+            .map_err(|e| e.with_hint(hint, token.span))?;
+        let mut arms = Vec::new();
+        loop {
+            while parser.match_token(TokenType::Semicolon) {
+                parser.consume();
+            }
+            if parser.match_token(TokenType::Keyword(Keyword::Esac)) {
+                break;
+            }
+            // `(pattern)` is allowed as well as `pattern)`
+            if parser.match_token(TokenType::LPar) {
+                parser.consume();
+            }
+            let mut patterns = Vec::new();
+            loop {
+                if !parser.match_word() {
+                    return Err(ParseError::new(
+                        vec![TokenType::Identifier, TokenType::Keyword(Keyword::Esac)],
+                        parser.look_ahead_type(),
+                        parser.look_ahead_span(),
+                    )
+                    .with_hint(hint, token.span));
+                }
+                let next = parser.consume();
+                patterns.push(parse_word(parser, next)?.to_string());
+                if !parser.match_token(TokenType::Pipe) {
+                    break;
+                }
+                parser.consume();
+            }
+            parser
+                .consume_expected(TokenType::RPar)
+                .map_err(|e| e.with_hint(hint, token.span))?;
+            while parser.match_token(TokenType::Semicolon) {
+                parser.consume();
+            }
+            let body = parser.parse_expression_empty()?;
+            let terminator = match parser.look_ahead_type() {
+                TokenType::CaseEnd(t) => {
+                    parser.consume();
+                    t
+                }
+                // the last arm may leave out its `;;`
+                TokenType::Keyword(Keyword::Esac) => CaseTerminator::Break,
+                found => {
+                    return Err(ParseError::new(
+                        vec![
+                            TokenType::CaseEnd(CaseTerminator::Break),
+                            TokenType::Keyword(Keyword::Esac),
+                        ],
+                        found,
+                        parser.look_ahead_span(),
+                    )
+                    .with_hint(hint, token.span))
+                }
+            };
+            arms.push(CaseArm {
+                patterns,
+                body,
+                terminator,
+            });
+        }
+        let closing = parser.consume();
+        Ok(Ast::Case {
+            word,
+            arms,
+            span: token.span.merge(closing.span),
+        })
+    }
+}
+
+/// Parses the body of a function named `name` once its `()` is consumed.
+fn parse_function_body(parser: &mut Parser, name: String, span: Span) -> Result<Ast, ParseError> {
+    while parser.match_token(TokenType::Semicolon) {
+        parser.consume();
+    }
+    if parser.match_token(TokenType::Null) || parser.match_list_end() {
+        return Err(ParseError::new(
+            vec![TokenType::LBrace],
+            parser.look_ahead_type(),
+            parser.look_ahead_span(),
+        )
+        .with_hint("function defined here", span));
+    }
+    // the body ends where a pipe or a list operator starts
+    let body = parser.parse_expression(8)?;
+    let span = span.merge(body.span());
+    Ok(Ast::Function {
+        name,
+        body: Box::from(body),
+        span,
+    })
+}
+
+impl PrefixParselet for FunctionParselet {
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        let name = match parser.consume_expected(TokenType::Identifier)?.token {
+            Token::Identifier(s) => s,
+            t => t.to_string(),
+        };
+        if parser.match_token(TokenType::LPar) {
+            parser.consume();
+            parser.consume_expected(TokenType::RPar)?;
+        }
+        parse_function_body(parser, name, token.span)
+    }
+}
+
+impl PrefixParselet for NotParselet {
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        // `! a | b && c` negates the pipeline, not the whole list
+        let operand = parser.parse_expression(5)?;
+        if operand == Ast::Nil {
+            return Err(ParseError::new(
+                vec![TokenType::Identifier],
+                parser.look_ahead_type(),
+                parser.look_ahead_span(),
+            ));
+        }
+        let span = token.span.merge(operand.span());
+        Ok(Ast::Node {
+            value: Parameters::Not,
+            left: Box::from(operand),
+            right: Box::from(Ast::Nil),
+            span,
+        })
+    }
+}
+
+impl PrefixParselet for TestParselet {
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        let outer = parser.set_in_test(true);
+        let expression = parser.parse_expression_empty();
+        parser.set_in_test(outer);
+        let expression = expression?;
+        if expression == Ast::Nil {
+            return Err(ParseError::new(
+                vec![TokenType::Identifier],
+                parser.look_ahead_type(),
+                parser.look_ahead_span(),
+            ));
+        }
+        let closing = parser
+            .consume_expected(TokenType::Keyword(Keyword::CloseTest))
+            .map_err(|e| e.with_hint("unclosed `[[` opened here", token.span))?;
+        Ok(Ast::Test {
+            expression: Box::from(expression),
+            span: token.span.merge(closing.span),
+        })
+    }
+}
+
+/// The words of one test inside `[[ ]]`. The word after an unquoted `=~`
+/// is the regular expression as written, so that its `(` and `|` need no
+/// quoting; quoted as a whole, it matches literally.
+impl PrefixParselet for TestWordsParselet {
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        let mut words = Vec::new();
+        let mut span = token.span;
+        let mut next = Some(token);
+        while let Some(token) = next.take() {
+            let (word, quoted) = parse_quoted_word(parser, token)?;
+            span = span.merge(word.span());
+            let text = word.to_string();
+            let regex = text == "=~" && !quoted;
+            words.push(TestWord { text, quoted });
+            if regex {
+                let (word, end) = parse_regex(parser)?;
+                span = span.merge(end);
+                words.push(word);
+                break;
+            }
+            if parser.match_word() {
+                next = Some(parser.consume());
+            }
+        }
+        Ok(Ast::new_spanned(Parameters::TestWords(words), span))
+    }
+}
+
+/// The text of a regular expression, which ends as a word does, or at the
+/// `]]`, `&&`, `||` or `)` after it, but not inside its parentheses.
+fn parse_regex(parser: &mut Parser) -> Result<(TestWord, Span), ParseError> {
+    let first = parser.look_ahead_span();
+    let mut last: Option<Span> = None;
+    let mut depth = 0;
+    loop {
+        let ends_word = last.is_some_and(|last| parser.look_ahead_span().start != last.end);
+        if parser.match_test_end()
+            || parser.match_token(TokenType::Null)
+            || (depth == 0
+                && (ends_word
+                    || parser.match_token(TokenType::And)
+                    || parser.match_token(TokenType::Or)
+                    || parser.match_token(TokenType::RPar)))
+        {
+            break;
+        }
+        let next = parser.consume();
+        last = Some(next.span);
+        match next.token {
+            Token::LPar => depth += 1,
+            Token::RPar => depth -= 1,
+            Token::Quote => {
+                while !parser.match_token(TokenType::Quote) && !parser.match_token(TokenType::Null)
+                {
+                    parser.consume();
                 }
+                let closing = parser
+                    .consume_expected(TokenType::Quote)
+                    .map_err(|e| e.with_hint("unclosed `\"` opened here", next.span))?;
+                last = Some(closing.span);
             }
-            parser.consume_expected(TokenType::Quote);
+            _ => (),
         }
+    }
+    let Some(last) = last else {
+        return Err(ParseError::new(
+            vec![TokenType::Identifier],
+            parser.look_ahead_type(),
+            first,
+        ));
+    };
+    let text = parser.text(first.start, last.end);
+    let word = match text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        Some(inner) if !inner.contains('"') => TestWord {
+            text: inner.to_string(),
+            quoted: true,
+        },
+        _ => TestWord {
+            text: text.to_string(),
+            quoted: false,
+        },
+    };
+    Ok((word, first.merge(last)))
+}
 
-        Ast::Node {
-            value: crate::parsing::ast::Parameters::Str(str.trim().to_string()),
-            left: Box::new(Ast::Nil),
-            right: Box::new(Ast::Nil),
+impl PrefixParselet for CommandParselet {
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        let name = parse_word(parser, token)?;
+        if parser.match_token(TokenType::LPar) {
+            // `name() body` defines a function
+            parser.consume();
+            parser.consume_expected(TokenType::RPar)?;
+            return parse_function_body(parser, name.to_string(), name.span());
+        }
+        let mut span = name.span();
+        let mut args = Vec::new();
+        while parser.match_word() {
+            let next = parser.consume();
+            let word = parse_word(parser, next)?;
+            span = span.merge(word.span());
+            args.push(word.to_string());
+        }
+        if args.is_empty() {
+            return Ok(name);
+        }
+        Ok(Ast::new_spanned(
+            Parameters::Call(name.to_string(), args),
+            span,
+        ))
+    }
+}
+
+impl PrefixParselet for QuoteParselet {
+    fn parse(&self, parser: &mut Parser, token: SpannedToken) -> Result<Ast, ParseError> {
+        // the tokens inside are skipped, the string is the text as written
+        while !parser.match_token(TokenType::Quote) && !parser.match_token(TokenType::Null) {
+            parser.consume();
         }
+        let closing = parser
+            .consume_expected(TokenType::Quote)
+            .map_err(|e| e.with_hint("unclosed `\"` opened here", token.span))?;
+        let str = parser.text(token.span.end, closing.span.start).to_string();
+        Ok(Ast::new_spanned(
+            Parameters::Str(str),
+            token.span.merge(closing.span),
+        ))
     }
 }
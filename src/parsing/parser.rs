@@ -1,38 +1,70 @@
 use std::slice::Iter;
 
-use crate::lexing::token::{Token, TokenType};
+use crate::lexing::token::{Keyword, Span, SpannedToken, Token, TokenType};
 
 use super::{
     ast::Ast,
+    error::ParseError,
     parselets::{
-        infix_parselet::{InfixParselet, NullParset, OperatorInfixParselet},
+        infix_parselet::{
+            InfixParselet, NullParset, OperatorInfixParselet, RedirectionInfixParselet,
+        },
         prefix_parselet::{
-            GroupParselet, NullParselet, OperatorPrefixParselet, PrefixParselet, QuoteParselet,
-            ValueParselet,
+            BraceGroupParselet, CaseParselet, CommandParselet, ForParselet, FunctionParselet,
+            GroupParselet, IfParselet, NotParselet, NullParselet, OperatorPrefixParselet,
+            PrefixParselet, TestParselet, TestWordsParselet, ValueParselet, WhileParselet,
         },
     },
 };
 
 #[derive(Clone)]
 pub struct Parser<'a> {
-    tokens: Iter<'a, Token>,
-    read: Vec<Token>,
+    /// The text the tokens were lexed from, for words taken as written
+    source: &'a str,
+    tokens: Iter<'a, SpannedToken>,
+    read: Vec<SpannedToken>,
+    eof: Span,
+    /// Inside `[[ ]]`, where `]]` ends the expression instead of being a word.
+    in_test: bool,
 }
 
-pub fn init_calc_parser(input: &Vec<Token>) -> Parser {
+pub fn init_calc_parser<'a>(source: &'a str, input: &'a [SpannedToken]) -> Parser<'a> {
+    let eof = match input.last() {
+        None => Span::default(),
+        Some(t) => Span::new(
+            t.span.end,
+            t.span.end,
+            t.span.line,
+            t.span.column
+                + source
+                    .get(t.span.start..t.span.end)
+                    .map_or(0, |text| text.chars().count()),
+        ),
+    };
     Parser {
+        source,
         tokens: input.iter(),
         read: Vec::new(),
+        eof,
+        in_test: false,
     }
 }
 
 impl Parser<'_> {
-    pub fn parse(&mut self) -> Ast {
-        self.parse_expression_empty()
+    pub fn parse(&mut self) -> Result<Ast, ParseError> {
+        let ast = self.parse_expression_empty()?;
+        let next = self.look_ahead(0);
+        if self.match_list_end() {
+            return Err(ParseError::unexpected(
+                next.token.to_token_type(),
+                next.span,
+            ));
+        }
+        Ok(ast)
     }
 
     #[cfg(not(tarpaulin_include))]
-    fn look_ahead(&mut self, distance: usize) -> Token {
+    fn look_ahead(&mut self, distance: usize) -> SpannedToken {
         while distance >= self.read.len() {
             match self.tokens.next() {
                 None => break,
@@ -40,69 +72,123 @@ impl Parser<'_> {
             }
         }
         match self.read.get(distance) {
-            None => Token::Null,
+            None => SpannedToken::new(Token::Null, self.eof),
             Some(t) => t.clone(),
         }
     }
 
-    pub fn parse_expression(&mut self, precedence: i64) -> Ast {
+    pub fn parse_expression(&mut self, precedence: i64) -> Result<Ast, ParseError> {
+        // an empty list is left for whoever opened it to complain about
+        if self.match_list_end() {
+            return Ok(Ast::Nil);
+        }
         let mut token = self.consume();
-        let prefix = self
-            .clone()
-            .get_prefix_parselet(token.clone().to_token_type());
+        let token_type = token.token.to_token_type();
+        let prefix = match self.clone().get_prefix_parselet(token_type.clone()) {
+            None => return Err(ParseError::unexpected(token_type, token.span)),
+            Some(p) => p,
+        };
 
-        let mut left = prefix.unwrap().parse(self, token.clone());
+        let mut left = prefix.parse(self, token)?;
         while precedence < self.get_precedence() {
             token = self.consume();
-            let parser = self
-                .clone()
-                .get_infix_parselet(token.clone().to_token_type())
-                .unwrap();
-            left = parser.parse(self, &left, token);
+            let token_type = token.token.to_token_type();
+            let parser = match self.clone().get_infix_parselet(token_type.clone()) {
+                None => return Err(ParseError::unexpected(token_type, token.span)),
+                Some(p) => p,
+            };
+            left = parser.parse(self, &left, token)?;
         }
-        left
+        Ok(left)
     }
 
-    pub fn parse_expression_empty(&mut self) -> Ast {
+    pub fn parse_expression_empty(&mut self) -> Result<Ast, ParseError> {
         self.parse_expression(0)
     }
 
-    pub fn consume(&mut self) -> Token {
+    pub fn consume(&mut self) -> SpannedToken {
         self.look_ahead(0);
-        if self.read.len() == 0 {
-            return Token::Null;
+        if self.read.is_empty() {
+            return SpannedToken::new(Token::Null, self.eof);
         }
         self.read.remove(0)
     }
 
+    pub fn eof_span(&self) -> Span {
+        self.eof
+    }
+
+    /// The source text between `start` and `end`.
+    pub fn text(&self, start: usize, end: usize) -> &str {
+        &self.source[start..end]
+    }
+
+    pub fn look_ahead_type(&mut self) -> TokenType {
+        self.look_ahead(0).token.to_token_type()
+    }
+
+    pub fn look_ahead_span(&mut self) -> Span {
+        self.look_ahead(0).span
+    }
+
+    /// Whether the next token closes the list being parsed.
+    pub fn match_list_end(&mut self) -> bool {
+        matches!(
+            self.look_ahead_type(),
+            TokenType::RPar
+                | TokenType::RBrace
+                | TokenType::Keyword(Keyword::Then)
+                | TokenType::Keyword(Keyword::Elif)
+                | TokenType::Keyword(Keyword::Else)
+                | TokenType::Keyword(Keyword::Fi)
+                | TokenType::Keyword(Keyword::Do)
+                | TokenType::Keyword(Keyword::Done)
+                | TokenType::Keyword(Keyword::Esac)
+                | TokenType::CaseEnd(_)
+        ) || self.match_test_end()
+    }
+
+    pub fn match_test_end(&mut self) -> bool {
+        self.in_test && self.match_token(TokenType::Keyword(Keyword::CloseTest))
+    }
+
+    /// Switches `[[ ]]` parsing on or off, returning the previous state.
+    pub fn set_in_test(&mut self, in_test: bool) -> bool {
+        std::mem::replace(&mut self.in_test, in_test)
+    }
+
+    /// Whether the next token can start or continue a shell word.
+    pub fn match_word(&mut self) -> bool {
+        matches!(
+            self.look_ahead_type(),
+            TokenType::Identifier
+                | TokenType::Int
+                | TokenType::Float
+                | TokenType::Bool
+                | TokenType::Dash
+                | TokenType::Tilde
+                | TokenType::Quote
+                | TokenType::Keyword(_)
+        ) && !self.match_test_end()
+    }
+
     pub fn match_token(&mut self, expected: TokenType) -> bool {
         let token = self.look_ahead(0);
-        if token.to_token_type() != expected {
-            return false;
-        }
-        return true;
+        token.token.to_token_type() == expected
     }
 
-    pub fn consume_expected(&mut self, expected: TokenType) -> Token {
-        self.look_ahead(0);
-        if self.read.len() == 0 {
-            return Token::Null;
-        }
-        match self.read.remove(0) {
-            t => {
-                if t.to_token_type() == expected {
-                    t
-                } else {
-                    println!("error!");
-                    Token::Null
-                }
-            }
+    pub fn consume_expected(&mut self, expected: TokenType) -> Result<SpannedToken, ParseError> {
+        let t = self.look_ahead(0);
+        let found = t.token.to_token_type();
+        if found != expected {
+            return Err(ParseError::new(vec![expected], found, t.span));
         }
+        Ok(self.consume())
     }
     fn get_precedence(&mut self) -> i64 {
         let p: Option<Box<dyn InfixParselet>> = self
             .clone()
-            .get_infix_parselet(self.look_ahead(0).to_token_type());
+            .get_infix_parselet(self.look_ahead(0).token.to_token_type());
         match p {
             None => 0,
             Some(t) => (*t).get_precedence(),
@@ -111,43 +197,75 @@ impl Parser<'_> {
 
     pub fn get_infix_parselet(self, token_type: TokenType) -> Option<Box<dyn InfixParselet>> {
         match token_type {
+            TokenType::Semicolon => Some(Box::from(OperatorInfixParselet {
+                is_right: false,
+                precedence: 1,
+            })),
             TokenType::Or => Some(Box::from(OperatorInfixParselet {
                 is_right: false,
-                precedence: 10,
+                precedence: 5,
             })),
             TokenType::And => Some(Box::from(OperatorInfixParselet {
                 is_right: false,
-                precedence: 10,
+                precedence: 5,
             })),
             TokenType::Pipe => Some(Box::from(OperatorInfixParselet {
                 is_right: false,
                 precedence: 8,
             })),
-            TokenType::LeftRedirection => Some(Box::from(OperatorInfixParselet {
-                is_right: false,
-                precedence: 9,
-            })),
-            TokenType::RightRedirection => Some(Box::from(OperatorInfixParselet {
-                is_right: false,
-                precedence: 9,
-            })),
+            TokenType::LeftRedirection => {
+                Some(Box::from(RedirectionInfixParselet { precedence: 9 }))
+            }
+            TokenType::RightRedirection => {
+                Some(Box::from(RedirectionInfixParselet { precedence: 9 }))
+            }
             _ => Some(Box::from(NullParset {})),
         }
     }
 
     pub fn get_prefix_parselet(self, token_type: TokenType) -> Option<Box<dyn PrefixParselet>> {
         match token_type {
+            // inside `[[ ]]`, words are those of a test such as `1 -lt 2`
+            TokenType::Int
+            | TokenType::Float
+            | TokenType::Dash
+            | TokenType::Identifier
+            | TokenType::Tilde
+            | TokenType::Bool
+            | TokenType::Quote
+                if self.in_test =>
+            {
+                Some(Box::from(TestWordsParselet {}))
+            }
             TokenType::Int => Some(Box::from(ValueParselet {})),
             TokenType::Float => Some(Box::from(ValueParselet {})),
-            TokenType::Identifier => Some(Box::from(ValueParselet {})),
-            TokenType::Bool => Some(Box::from(ValueParselet {})),
+            TokenType::Dash => Some(Box::from(CommandParselet {})),
+            TokenType::Identifier => Some(Box::from(CommandParselet {})),
+            TokenType::Tilde => Some(Box::from(CommandParselet {})),
+            TokenType::Bool => Some(Box::from(CommandParselet {})),
             TokenType::Or => Some(Box::from(OperatorPrefixParselet {})),
             TokenType::And => Some(Box::from(OperatorPrefixParselet {})),
             TokenType::LPar => Some(Box::from(GroupParselet {})),
+            TokenType::LBrace => Some(Box::from(BraceGroupParselet {})),
+            TokenType::Keyword(Keyword::If) => Some(Box::from(IfParselet {})),
+            TokenType::Keyword(Keyword::While) => Some(Box::from(WhileParselet { until: false })),
+            TokenType::Keyword(Keyword::Until) => Some(Box::from(WhileParselet { until: true })),
+            TokenType::Keyword(Keyword::For) => Some(Box::from(ForParselet {})),
+            TokenType::Keyword(Keyword::Case) => Some(Box::from(CaseParselet {})),
+            TokenType::Keyword(Keyword::Function) => Some(Box::from(FunctionParselet {})),
+            TokenType::Keyword(Keyword::OpenTest) => Some(Box::from(TestParselet {})),
+            TokenType::Keyword(Keyword::Bang) => Some(Box::from(NotParselet {})),
+            // `not` only negates inside `[[ ]]`, elsewhere it names a command
+            TokenType::Keyword(Keyword::Not) if self.in_test => Some(Box::from(NotParselet {})),
+            TokenType::Keyword(Keyword::Not) => Some(Box::from(CommandParselet {})),
+            TokenType::Keyword(_) => None,
             TokenType::LeftRedirection => Some(Box::from(OperatorPrefixParselet {})),
             TokenType::RightRedirection => Some(Box::from(OperatorPrefixParselet {})),
             TokenType::Pipe => Some(Box::from(OperatorPrefixParselet {})),
-            TokenType::Quote => Some(Box::from(QuoteParselet {})),
+            TokenType::Quote => Some(Box::from(CommandParselet {})),
+            TokenType::RPar => None,
+            TokenType::RBrace => None,
+            TokenType::Semicolon => None,
             _ => Some(Box::from(NullParselet {})),
         }
     }
@@ -156,8 +274,11 @@ impl Parser<'_> {
 #[cfg(test)]
 mod test {
     use crate::{
-        lexing::{lexer::Lexer, token::Token},
-        parsing::ast::{Ast, Parameters},
+        lexing::{
+            lexer::Lexer,
+            token::{CaseTerminator, Span, Token, TokenType},
+        },
+        parsing::ast::{Ast, CaseArm, Parameters, TestWord},
     };
 
     use super::init_calc_parser;
@@ -167,17 +288,16 @@ mod test {
         let data = Lexer {
             str: "(".to_string(),
         };
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        assert_eq!(
-            parser.consume_expected(crate::lexing::token::TokenType::Or),
-            Token::Null
-        );
-        assert_eq!(parser.consume(), Token::Null);
-        assert_eq!(
-            parser.consume_expected(crate::lexing::token::TokenType::And),
-            Token::Null
-        );
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert!(parser
+            .consume_expected(crate::lexing::token::TokenType::Or)
+            .is_err());
+        assert_eq!(parser.consume().token, Token::LPar);
+        assert_eq!(parser.consume().token, Token::Null);
+        assert!(parser
+            .consume_expected(crate::lexing::token::TokenType::And)
+            .is_err());
     }
 
     #[test]
@@ -186,32 +306,42 @@ mod test {
             value: Parameters::Int(1),
             left: Box::from(Ast::Nil),
             right: Box::from(Ast::Nil),
+            span: Span::default(),
         };
 
         let data = Lexer {
             str: "1".to_string(),
         };
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected)
     }
 
     #[test]
     pub fn test_parse_float() {
         let expected = Ast::Node {
-            value: Parameters::Float(1.0),
+            value: Parameters::Float(1.5),
             left: Box::from(Ast::Nil),
             right: Box::from(Ast::Nil),
+            span: Span::default(),
         };
 
+        let data = Lexer {
+            str: "1.5".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
+        assert_eq!(value, expected);
+
+        // one that would not show as written is a command name
         let data = Lexer {
             str: "1.0".to_string(),
         };
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
-        assert_eq!(value, expected)
+        let datalex = data.lex_spanned();
+        let value = init_calc_parser(&data.str, &datalex).parse().unwrap();
+        assert_eq!(value, Ast::new(Parameters::Identifier("1.0".to_string())));
     }
 
     #[test]
@@ -220,14 +350,15 @@ mod test {
             value: Parameters::Bool(false),
             left: Box::from(Ast::Nil),
             right: Box::from(Ast::Nil),
+            span: Span::default(),
         };
 
         let data = Lexer {
             str: "false".to_string(),
         };
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected)
     }
 
@@ -237,14 +368,15 @@ mod test {
             value: Parameters::Identifier("test".to_string()),
             left: Box::from(Ast::Nil),
             right: Box::from(Ast::Nil),
+            span: Span::default(),
         };
 
         let data = Lexer {
             str: "test".to_string(),
         };
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected)
     }
 
@@ -254,10 +386,56 @@ mod test {
         let data = Lexer {
             str: "\"test 1 2 1 2\"".to_string(),
         };
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
-        assert_eq!(value, expected)
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
+        assert_eq!(value, expected);
+        let data = Lexer {
+            str: "echo \" a \"".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let value = init_calc_parser(&data.str, &datalex).parse().unwrap();
+        assert_eq!(
+            value,
+            Ast::new(Parameters::Call(
+                "echo".to_string(),
+                vec![" a ".to_string()]
+            ))
+        );
+        // operators, numbers and whitespace are kept as written
+        let data = Lexer {
+            str: "echo \"this and that\" \"a || b\" \"1.50\" \"x\t  y%\" -\"1.50\"".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let value = init_calc_parser(&data.str, &datalex).parse().unwrap();
+        assert_eq!(
+            value,
+            Ast::new(Parameters::Call(
+                "echo".to_string(),
+                vec![
+                    "this and that".to_string(),
+                    "a || b".to_string(),
+                    "1.50".to_string(),
+                    "x\t  y%".to_string(),
+                    "-1.50".to_string(),
+                ]
+            ))
+        );
+        // so are unquoted numbers
+        let data = Lexer {
+            str: "echo 1.50 007 1.0 1 2.5".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let value = init_calc_parser(&data.str, &datalex).parse().unwrap();
+        assert_eq!(
+            value,
+            Ast::new(Parameters::Call(
+                "echo".to_string(),
+                ["1.50", "007", "1.0", "1", "2.5"]
+                    .map(String::from)
+                    .to_vec()
+            ))
+        )
     }
 
     #[test]
@@ -266,15 +444,16 @@ mod test {
             value: Parameters::And,
             left: Box::from(Ast::new(Parameters::Bool(true))),
             right: Box::from(Ast::Nil),
+            span: Span::default(),
         };
 
         let data = Lexer {
             str: "&& true".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
@@ -284,15 +463,16 @@ mod test {
             value: Parameters::Or,
             left: Box::from(Ast::new(Parameters::Bool(true))),
             right: Box::from(Ast::Nil),
+            span: Span::default(),
         };
 
         let data = Lexer {
             str: "or true".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
@@ -302,15 +482,16 @@ mod test {
             value: Parameters::LeftRedirection,
             left: Box::from(Ast::new(Parameters::Bool(true))),
             right: Box::from(Ast::Nil),
+            span: Span::default(),
         };
 
         let data = Lexer {
             str: "> true".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
@@ -320,15 +501,16 @@ mod test {
             value: Parameters::RightRedirection,
             left: Box::from(Ast::new(Parameters::Bool(true))),
             right: Box::from(Ast::Nil),
+            span: Span::default(),
         };
 
         let data = Lexer {
             str: "< true".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
@@ -338,33 +520,40 @@ mod test {
             value: Parameters::Pipe,
             left: Box::from(Ast::new(Parameters::Bool(true))),
             right: Box::from(Ast::Nil),
+            span: Span::default(),
         };
 
         let data = Lexer {
             str: "| true".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
     #[test]
     pub fn test_group_prefix() {
         let expected = Ast::Node {
-            value: Parameters::Pipe,
-            left: Box::from(Ast::new(Parameters::Bool(true))),
+            value: Parameters::Subshell,
+            left: Box::from(Ast::Node {
+                value: Parameters::Pipe,
+                left: Box::from(Ast::new(Parameters::Bool(true))),
+                right: Box::from(Ast::Nil),
+                span: Span::default(),
+            }),
             right: Box::from(Ast::Nil),
+            span: Span::default(),
         };
 
         let data = Lexer {
             str: "(| true)".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
@@ -375,9 +564,9 @@ mod test {
             str: "&".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
@@ -387,14 +576,15 @@ mod test {
             value: Parameters::And,
             left: Box::from(Ast::new(Parameters::Int(1))),
             right: Box::from(Ast::new(Parameters::Int(1))),
+            span: Span::default(),
         };
         let data = Lexer {
             str: "1 && 1".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
@@ -404,14 +594,15 @@ mod test {
             value: Parameters::Or,
             left: Box::from(Ast::new(Parameters::Int(1))),
             right: Box::from(Ast::new(Parameters::Int(1))),
+            span: Span::default(),
         };
         let data = Lexer {
             str: "1 or 1".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
@@ -421,14 +612,15 @@ mod test {
             value: Parameters::Pipe,
             left: Box::from(Ast::new(Parameters::Int(1))),
             right: Box::from(Ast::new(Parameters::Int(1))),
+            span: Span::default(),
         };
         let data = Lexer {
             str: "1 | 1".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
@@ -438,14 +630,15 @@ mod test {
             value: Parameters::LeftRedirection,
             left: Box::from(Ast::new(Parameters::Int(1))),
             right: Box::from(Ast::new(Parameters::Int(1))),
+            span: Span::default(),
         };
         let data = Lexer {
             str: "1 > 1".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
@@ -455,14 +648,15 @@ mod test {
             value: Parameters::RightRedirection,
             left: Box::from(Ast::new(Parameters::Int(1))),
             right: Box::from(Ast::new(Parameters::Int(1))),
+            span: Span::default(),
         };
         let data = Lexer {
             str: "1 < 1".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
     }
 
@@ -472,14 +666,569 @@ mod test {
             value: Parameters::Int(1),
             left: Box::from(Ast::Nil),
             right: Box::from(Ast::Nil),
+            span: Span::default(),
         };
         let data = Lexer {
             str: "1 & 1".to_string(),
         };
 
-        let datalex = data.lex();
-        let parser = &mut init_calc_parser(&datalex);
-        let value = parser.parse();
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    pub fn test_infix_span() {
+        let data = Lexer {
+            str: "ls | cat".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
+        assert_eq!(value.span(), Span::new(0, 8, 1, 1));
+    }
+
+    #[test]
+    pub fn test_unclosed_group() {
+        let data = Lexer {
+            str: "(ls | cat".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap_err();
+        assert_eq!(value.expected, vec![TokenType::RPar]);
+        assert_eq!(value.found, TokenType::Null);
+        assert_eq!(value.span, Span::new(9, 9, 1, 10));
+        assert_eq!(value.hint.unwrap().span, Span::new(0, 1, 1, 1));
+
+        // columns count characters, not bytes
+        let data = Lexer {
+            str: "(ls | café".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let value = init_calc_parser(&data.str, &datalex).parse().unwrap_err();
+        assert_eq!(value.span, Span::new(11, 11, 1, 11));
+    }
+
+    #[test]
+    pub fn test_unclosed_quote() {
+        let data = Lexer {
+            str: "\"abc".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap_err();
+        assert_eq!(value.expected, vec![TokenType::Quote]);
+        assert_eq!(value.found, TokenType::Null);
+    }
+
+    #[test]
+    pub fn test_unexpected_rpar() {
+        let data = Lexer {
+            str: "ls )".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap_err();
+        assert_eq!(value.to_string(), "unexpected `)`");
+        assert_eq!(value.span, Span::new(3, 4, 1, 4));
+    }
+
+    #[test]
+    pub fn test_incomplete_input() {
+        for str in ["ls |", "ls &&", "ls or", "(ls", "\"abc", "(ls | cat"] {
+            let data = Lexer {
+                str: str.to_string(),
+            };
+            let datalex = data.lex_spanned();
+            let parser = &mut init_calc_parser(&data.str, &datalex);
+            assert!(parser.parse().unwrap_err().is_incomplete(), "{str}");
+        }
+    }
+
+    #[test]
+    pub fn test_complete_after_continuation() {
+        let expected = Ast::Node {
+            value: Parameters::Pipe,
+            left: Box::from(Ast::new(Parameters::Identifier("ls".to_string()))),
+            right: Box::from(Ast::new(Parameters::Identifier("cat".to_string()))),
+            span: Span::default(),
+        };
+        let data = Lexer {
+            str: "ls |\ncat".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert_eq!(parser.parse().unwrap(), expected);
+    }
+
+    #[test]
+    pub fn test_parse_call() {
+        let expected = Ast::new(Parameters::Call(
+            "ls".to_string(),
+            vec!["-la".to_string(), "~/src".to_string(), "a b".to_string()],
+        ));
+        let data = Lexer {
+            str: "ls -la ~/src \"a b\"".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
         assert_eq!(value, expected);
+        assert_eq!(value.span(), Span::new(0, 18, 1, 1));
+    }
+
+    #[test]
+    pub fn test_subshell_redirection() {
+        let expected = Ast::Node {
+            value: Parameters::LeftRedirection,
+            left: Box::from(Ast::Node {
+                value: Parameters::Subshell,
+                left: Box::from(Ast::Node {
+                    value: Parameters::Semicolon,
+                    left: Box::from(Ast::new(Parameters::Identifier("a".to_string()))),
+                    right: Box::from(Ast::new(Parameters::Identifier("b".to_string()))),
+                    span: Span::default(),
+                }),
+                right: Box::from(Ast::Nil),
+                span: Span::default(),
+            }),
+            right: Box::from(Ast::new(Parameters::Identifier("out.txt".to_string()))),
+            span: Span::default(),
+        };
+        let data = Lexer {
+            str: "(a; b) > out.txt".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert_eq!(parser.parse().unwrap(), expected);
+    }
+
+    #[test]
+    pub fn test_list_precedence() {
+        let data = Lexer {
+            str: "a | b && c; d".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert_eq!(parser.parse().unwrap().to_string(), "a | b && c ; d");
+        let data = Lexer {
+            str: "a; b;".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
+        let expected = Ast::Node {
+            value: Parameters::Semicolon,
+            left: Box::from(Ast::new(Parameters::Identifier("a".to_string()))),
+            right: Box::from(Ast::new(Parameters::Identifier("b".to_string()))),
+            span: Span::default(),
+        };
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    pub fn test_missing_operand() {
+        let data = Lexer {
+            str: "(ls |)".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap_err();
+        assert_eq!(value.to_string(), "expected identifier, found `)`");
+        assert!(!value.is_incomplete());
+    }
+
+    #[test]
+    pub fn test_brace_group() {
+        let expected = Ast::Node {
+            value: Parameters::LeftRedirection,
+            left: Box::from(Ast::Node {
+                value: Parameters::Group,
+                left: Box::from(Ast::Node {
+                    value: Parameters::Semicolon,
+                    left: Box::from(Ast::new(Parameters::Identifier("a".to_string()))),
+                    right: Box::from(Ast::new(Parameters::Identifier("b".to_string()))),
+                    span: Span::default(),
+                }),
+                right: Box::from(Ast::Nil),
+                span: Span::default(),
+            }),
+            right: Box::from(Ast::new(Parameters::Identifier("out".to_string()))),
+            span: Span::default(),
+        };
+        let data = Lexer {
+            str: "{ a; b; } > out".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert_eq!(parser.parse().unwrap(), expected);
+    }
+
+    #[test]
+    pub fn test_unclosed_brace_group() {
+        let data = Lexer {
+            str: "{ a;".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap_err();
+        assert!(value.is_incomplete());
+        assert_eq!(value.hint.unwrap().message, "unclosed `{` opened here");
+        let data = Lexer {
+            str: "a }".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert_eq!(parser.parse().unwrap_err().to_string(), "unexpected `}`");
+    }
+
+    #[test]
+    pub fn test_if() {
+        let expected = Ast::If {
+            condition: Box::from(Ast::new(Parameters::Identifier("a".to_string()))),
+            then_branch: Box::from(Ast::new(Parameters::Call(
+                "echo".to_string(),
+                vec!["fi".to_string()],
+            ))),
+            else_branch: Box::from(Ast::If {
+                condition: Box::from(Ast::new(Parameters::Identifier("b".to_string()))),
+                then_branch: Box::from(Ast::new(Parameters::Identifier("c".to_string()))),
+                else_branch: Box::from(Ast::new(Parameters::Identifier("d".to_string()))),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        let data = Lexer {
+            str: "if a\nthen echo fi; elif b; then c\nelse d; fi".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
+        assert_eq!(value, expected);
+        assert_eq!(value.span(), Span::new(0, 44, 1, 1));
+    }
+
+    #[test]
+    pub fn test_if_errors() {
+        let data = Lexer {
+            str: "if a; then b".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap_err();
+        assert!(value.is_incomplete());
+        assert_eq!(value.hint.unwrap().message, "unclosed `if` opened here");
+
+        let data = Lexer {
+            str: "if a; fi".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap_err();
+        assert_eq!(value.to_string(), "expected `then`, found `fi`");
+
+        let data = Lexer {
+            str: "then".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert_eq!(parser.parse().unwrap_err().to_string(), "unexpected `then`");
+    }
+
+    #[test]
+    pub fn test_loops() {
+        let expected = Ast::While {
+            condition: Box::from(Ast::new(Parameters::Identifier("a".to_string()))),
+            body: Box::from(Ast::For {
+                variable: "x".to_string(),
+                words: Some(vec!["1".to_string(), "$y".to_string(), "done".to_string()]),
+                body: Box::from(Ast::new(Parameters::Identifier("b".to_string()))),
+                span: Span::default(),
+            }),
+            until: false,
+            span: Span::default(),
+        };
+        let data = Lexer {
+            str: "while a; do\nfor x in 1 $y done\ndo b; done; done".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
+        assert_eq!(value, expected);
+        assert_eq!(value.span(), Span::new(0, 47, 1, 1));
+    }
+
+    #[test]
+    pub fn test_arithmetic_for() {
+        let expected = Ast::ArithmeticFor {
+            init: "i=0".to_string(),
+            condition: "i < 10 && (i >= 0)".to_string(),
+            update: "i++".to_string(),
+            body: Box::from(Ast::new(Parameters::Identifier("b".to_string()))),
+            span: Span::default(),
+        };
+        let data = Lexer {
+            str: "for ((i=0; i < 10 && (i >= 0); i++)); do b; done".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert_eq!(parser.parse().unwrap(), expected);
+
+        let data = Lexer {
+            str: "for ((i=0; i++)); do b; done".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    pub fn test_unclosed_loop() {
+        let data = Lexer {
+            str: "until a; do b".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap_err();
+        assert!(value.is_incomplete());
+        assert_eq!(value.hint.unwrap().message, "unclosed `until` opened here");
+    }
+
+    #[test]
+    pub fn test_case() {
+        let data = Lexer {
+            str: "case $x in\n(a|b) echo ab;;\n*)\n  echo other\n  ;;&\nesac".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
+        let expected = Ast::Case {
+            word: "$x".to_string(),
+            arms: vec![
+                CaseArm {
+                    patterns: vec!["a".to_string(), "b".to_string()],
+                    body: Ast::new(Parameters::Call("echo".to_string(), vec!["ab".to_string()])),
+                    terminator: CaseTerminator::Break,
+                },
+                CaseArm {
+                    patterns: vec!["*".to_string()],
+                    body: Ast::new(Parameters::Call(
+                        "echo".to_string(),
+                        vec!["other".to_string()],
+                    )),
+                    terminator: CaseTerminator::Continue,
+                },
+            ],
+            span: Span::default(),
+        };
+        assert_eq!(value, expected);
+        assert_eq!(value.span().end, data.str.len());
+    }
+
+    #[test]
+    pub fn test_case_errors() {
+        for (str, message) in [
+            (
+                "case a in b) c",
+                "expected `;;` or `esac`, found end of input",
+            ),
+            ("case a b) c;; esac", "expected `in`, found identifier"),
+            (
+                "case a in ) c;; esac",
+                "expected identifier or `esac`, found `)`",
+            ),
+            ("echo a;; b", "unexpected `;;`"),
+        ] {
+            let data = Lexer {
+                str: str.to_string(),
+            };
+            let datalex = data.lex_spanned();
+            let parser = &mut init_calc_parser(&data.str, &datalex);
+            assert_eq!(parser.parse().unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    pub fn test_function_definition() {
+        let body = Ast::Node {
+            value: Parameters::Group,
+            left: Box::from(Ast::new(Parameters::Call(
+                "echo".to_string(),
+                vec!["$#".to_string()],
+            ))),
+            right: Box::from(Ast::Nil),
+            span: Span::default(),
+        };
+        let expected = Ast::Function {
+            name: "f".to_string(),
+            body: Box::from(body),
+            span: Span::default(),
+        };
+        for str in [
+            "f() { echo $#; }",
+            "f ()\n{\n echo $#\n}",
+            "function f { echo $#; }",
+            "function f() { echo $#; }",
+        ] {
+            let data = Lexer {
+                str: str.to_string(),
+            };
+            let datalex = data.lex_spanned();
+            let parser = &mut init_calc_parser(&data.str, &datalex);
+            let value = parser.parse().unwrap();
+            assert_eq!(value, expected);
+            assert_eq!(value.span().end, str.len());
+        }
+
+        let data = Lexer {
+            str: "f()".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert!(parser.parse().unwrap_err().is_incomplete());
+    }
+
+    #[test]
+    pub fn test_double_brackets() {
+        let call = |name: &str, args: &[&str]| {
+            Ast::new(Parameters::Call(
+                name.to_string(),
+                args.iter().map(|a| a.to_string()).collect(),
+            ))
+        };
+        let words = |words: &[(&str, bool)]| {
+            Ast::new(Parameters::TestWords(
+                words
+                    .iter()
+                    .map(|(text, quoted)| TestWord {
+                        text: text.to_string(),
+                        quoted: *quoted,
+                    })
+                    .collect(),
+            ))
+        };
+        let expected = Ast::Test {
+            expression: Box::from(Ast::Node {
+                value: Parameters::And,
+                left: Box::from(words(&[("-f", false), ("a", false)])),
+                right: Box::from(Ast::Node {
+                    value: Parameters::Subshell,
+                    left: Box::from(words(&[("1", false), ("-lt", false), ("$b", false)])),
+                    right: Box::from(Ast::Nil),
+                    span: Span::default(),
+                }),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        let data = Lexer {
+            str: "[[ -f a && ( 1 -lt $b ) ]]".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert_eq!(parser.parse().unwrap(), expected);
+
+        let data = Lexer {
+            str: "echo ]]".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert_eq!(parser.parse().unwrap(), call("echo", &["]]"]));
+
+        // the regular expression is taken as written, up to `]]`
+        let data = Lexer {
+            str: "[[ \"a\"b =~ ^(a| b)\\ c$ && a =~ \"(a)\" ]]".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let expected = Ast::Test {
+            expression: Box::from(Ast::Node {
+                value: Parameters::And,
+                left: Box::from(words(&[
+                    ("ab", true),
+                    ("=~", false),
+                    ("^(a| b)\\ c$", false),
+                ])),
+                right: Box::from(words(&[("a", false), ("=~", false), ("(a)", true)])),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        assert_eq!(parser.parse().unwrap(), expected);
+
+        for (str, message) in [
+            ("[[ a == b", "expected `]]`, found end of input"),
+            ("[[ ]]", "expected identifier, found `]]`"),
+            ("[[ a =~ ]]", "expected identifier, found `]]`"),
+            ("[[ a =~ a b ]]", "expected `]]`, found identifier"),
+        ] {
+            let data = Lexer {
+                str: str.to_string(),
+            };
+            let datalex = data.lex_spanned();
+            let parser = &mut init_calc_parser(&data.str, &datalex);
+            assert_eq!(parser.parse().unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    pub fn test_not() {
+        let leaf = |s: &str| Ast::new(Parameters::Identifier(s.to_string()));
+        let expected = Ast::Node {
+            value: Parameters::And,
+            left: Box::from(Ast::Node {
+                value: Parameters::Not,
+                left: Box::from(Ast::Node {
+                    value: Parameters::Pipe,
+                    left: Box::from(leaf("a")),
+                    right: Box::from(leaf("b")),
+                    span: Span::default(),
+                }),
+                right: Box::from(Ast::Nil),
+                span: Span::default(),
+            }),
+            right: Box::from(Ast::Node {
+                value: Parameters::Not,
+                left: Box::from(Ast::new(Parameters::Call(
+                    "test".to_string(),
+                    vec![
+                        "!".to_string(),
+                        "a".to_string(),
+                        "!=".to_string(),
+                        "not".to_string(),
+                    ],
+                ))),
+                right: Box::from(Ast::Nil),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        let data = Lexer {
+            str: "! a | b && ! test ! a != not".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        let value = parser.parse().unwrap();
+        assert_eq!(value, expected);
+        assert_eq!(value.to_string(), "! a | b && ! test ! a != not");
+
+        let data = Lexer {
+            str: "not a".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert_eq!(
+            parser.parse().unwrap(),
+            Ast::new(Parameters::Call("not".to_string(), vec!["a".to_string()]))
+        );
+
+        let data = Lexer {
+            str: "a && !".to_string(),
+        };
+        let datalex = data.lex_spanned();
+        let parser = &mut init_calc_parser(&data.str, &datalex);
+        assert!(parser.parse().unwrap_err().is_incomplete());
     }
 }
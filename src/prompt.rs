@@ -0,0 +1,510 @@
+use std::time::Duration;
+
+use ansi_term::Color;
+
+use crate::{config::parse_colour, git, interpreting::interpreter::Context};
+
+/// The prompt shown before a command: `$PS1` if set, otherwise the
+/// `prompt` of the configuration painted in `prompt_colour`.
+pub fn primary(ctx: &Context) -> String {
+    match ctx.get_variable("PS1") {
+        Some(ps1) => render(&ps1, ctx),
+        None => coloured(&ctx.config.prompt, ctx),
+    }
+}
+
+/// The prompt shown while a command is incomplete: `$PS2` or the
+/// `continuation_prompt` of the configuration.
+pub fn continuation(ctx: &Context) -> String {
+    match ctx.get_variable("PS2") {
+        Some(ps2) => render(&ps2, ctx),
+        None => coloured(&ctx.config.continuation_prompt, ctx),
+    }
+}
+
+/// The prompt shown at the right of the line: `$RPS1` or the
+/// `right_prompt` of the configuration, empty for none.
+pub fn right(ctx: &Context) -> String {
+    match ctx.get_variable("RPS1") {
+        Some(rps1) => render(&rps1, ctx),
+        None => render(&ctx.config.right_prompt, ctx),
+    }
+}
+
+/// What a prompt is replaced with once its command is entered, when
+/// `transient_prompt` is on.
+pub fn transient(ctx: &Context) -> String {
+    coloured(&ctx.config.transient_prompt_text, ctx)
+}
+
+fn coloured(template: &str, ctx: &Context) -> String {
+    let style = parse_colour(&ctx.config.prompt_colour).unwrap_or(Color::Cyan);
+    let mut text = expand_escapes(template, ctx);
+    if ctx.config.git_prompt {
+        let segment = git_segment(ctx);
+        if !segment.is_empty() {
+            text = format!("{segment} {text}");
+        }
+    }
+    wrap_escapes(&format!("{}{}{}", style.prefix(), text, style.suffix()))
+}
+
+fn git_segment(ctx: &Context) -> String {
+    git::segment(Duration::from_millis(ctx.config.git_budget_ms))
+}
+
+/// Expands the escapes of a prompt template and wraps every terminal
+/// escape sequence in `\x01`/`\x02`, so that the line editor knows it takes
+/// no room on screen:
+///
+/// - `\u` user, `\h` host up to the first `.`, `\H` full host
+/// - `\w` working directory with `$HOME` as `~`, `\W` its last component
+/// - `\t` 24-hour `HH:MM:SS`, `\T` 12-hour `HH:MM:SS`, `\@` 12-hour with
+///   am/pm, `\A` `HH:MM`, `\d` date as `Tue May 26`
+/// - `\D` time the last command took, as in `1.5s`
+/// - `\?` status of the last command, `\j` number of jobs, `\$` `#` for
+///   root and `$` otherwise
+/// - `\g` branch and state of the git repository, if there is one
+/// - `\s` shell name, `\v` and `\V` version
+/// - `\e`, `\a`, `\n`, `\r`, `\\`, `\NNN` octal character
+/// - `\[` and `\]` around characters that take no room, as in bash
+/// - `\c{colour}` switches to a colour of the configuration (`red`, `208`...)
+///   and `\c{reset}` back to the default
+pub fn render(template: &str, ctx: &Context) -> String {
+    wrap_escapes(&expand_escapes(template, ctx))
+}
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn expand_escapes(template: &str, ctx: &Context) -> String {
+    let mut result = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            result.push('\\');
+            break;
+        };
+        match escape {
+            'u' => result += &user(),
+            'h' => result += hostname().split('.').next().unwrap_or_default(),
+            'H' => result += &hostname(),
+            'w' => result += &working_directory(ctx, false),
+            'W' => result += &working_directory(ctx, true),
+            't' | 'T' | '@' | 'A' | 'd' => result += &now().format(escape),
+            'D' => result += &format_duration(ctx.last_duration),
+            '?' => result += &ctx.last_status.to_string(),
+            // tinysh has no job control yet, so no job ever runs in the background
+            'j' => result.push('0'),
+            '$' => result.push(if is_root() { '#' } else { '$' }),
+            'g' => result += &git_segment(ctx),
+            's' => result += "tinysh",
+            'v' => result += VERSION.rsplit_once('.').map_or(VERSION, |v| v.0),
+            'V' => result += VERSION,
+            'e' => result.push('\x1b'),
+            'a' => result.push('\x07'),
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            '\\' => result.push('\\'),
+            '[' => result.push('\x01'),
+            ']' => result.push('\x02'),
+            '0'..='7' => {
+                let mut code = escape.to_digit(8).unwrap_or_default();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                result.extend(char::from_u32(code));
+            }
+            'c' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                match name.as_str() {
+                    "reset" => result += "\x1b[0m",
+                    name => {
+                        if let Some(colour) = parse_colour(name) {
+                            result += &colour.prefix().to_string();
+                        }
+                    }
+                }
+            }
+            other => {
+                result.push('\\');
+                result.push(other);
+            }
+        }
+    }
+    result
+}
+
+/// Puts `\x01`/`\x02` around the `ESC [ ... final` sequences that are not
+/// already between a `\[` and a `\]`.
+fn wrap_escapes(str: &str) -> String {
+    let mut result = String::new();
+    let mut hidden = false;
+    let mut chars = str.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x01' => hidden = true,
+            '\x02' => hidden = false,
+            '\x1b' if !hidden && chars.peek() == Some(&'[') => {
+                result.push('\x01');
+                result.push(c);
+                result.push(chars.next().unwrap_or('['));
+                for c in chars.by_ref() {
+                    result.push(c);
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+                result.push('\x02');
+                continue;
+            }
+            _ => (),
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Number of columns the prompt takes on screen, without what is between
+/// `\x01` and `\x02`.
+pub fn visible_width(prompt: &str) -> usize {
+    let mut hidden = false;
+    prompt
+        .chars()
+        .filter(|c| match c {
+            '\x01' => {
+                hidden = true;
+                false
+            }
+            '\x02' => {
+                hidden = false;
+                false
+            }
+            _ => !hidden,
+        })
+        .count()
+}
+
+/// Adds a right prompt to the last line of `left`, drawn at the end of the
+/// line by moving the cursor there and back. It is hidden from the line
+/// editor so that it can be redrawn with the prompt; one which would not
+/// fit next to `left` is left out.
+pub fn with_right(left: &str, right: &str, columns: usize) -> String {
+    let width = visible_width(right);
+    let (before, last) = match left.rfind('\n') {
+        Some(i) => left.split_at(i + 1),
+        None => ("", left),
+    };
+    if width == 0 || visible_width(last) + width >= columns {
+        return left.to_string();
+    }
+    let right: String = right
+        .chars()
+        .filter(|c| !matches!(c, '\x01' | '\x02'))
+        .collect();
+    format!(
+        "{before}\x01\x1b7\x1b[{}G{right}\x1b8\x02{last}",
+        columns - width + 1
+    )
+}
+
+/// Number of screen lines a prompt and the line typed after it took.
+pub fn rows(prompt: &str, input: &str, columns: usize) -> usize {
+    let columns = columns.max(1);
+    let mut lines = prompt.split('\n');
+    let last = lines.next_back().unwrap_or_default();
+    let before = lines.count();
+    let width = visible_width(last) + input.chars().count();
+    before + width.div_ceil(columns).max(1)
+}
+
+/// The width of the terminal, or `$COLUMNS`, or 80.
+pub fn terminal_width() -> usize {
+    #[cfg(unix)]
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 {
+            return size.ws_col as usize;
+        }
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(80)
+}
+
+/// `350ms`, `1.5s`, `2m03s` or `1h05m`.
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    let secs = duration.as_secs();
+    if millis < 1000 {
+        format!("{millis}ms")
+    } else if secs < 60 {
+        format!("{}.{}s", secs, duration.subsec_millis() / 100)
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+fn working_directory(ctx: &Context, last_component: bool) -> String {
+    let cwd = match std::env::current_dir() {
+        Ok(dir) => dir.to_string_lossy().to_string(),
+        Err(_) => ctx.get_variable("PWD").unwrap_or_default(),
+    };
+    let home = ctx.get_variable("HOME").unwrap_or_default();
+    if last_component && cwd != home {
+        return match cwd.rsplit_once('/') {
+            Some((_, "")) | None => cwd,
+            Some((_, last)) => last.to_string(),
+        };
+    }
+    abbreviate_home(&cwd, &home)
+}
+
+/// `$HOME` and the paths under it start with `~` instead.
+pub fn abbreviate_home(path: &str, home: &str) -> String {
+    if home.is_empty() || home == "/" {
+        return path.to_string();
+    }
+    match path.strip_prefix(home) {
+        Some("") => "~".to_string(),
+        Some(rest) if rest.starts_with('/') => format!("~{rest}"),
+        _ => path.to_string(),
+    }
+}
+
+/// `$USER` or its equivalents, else the name of the user in the passwd
+/// database.
+fn user() -> String {
+    ["USER", "LOGNAME", "USERNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|u| !u.is_empty()))
+        .unwrap_or_else(user_from_passwd)
+}
+
+#[cfg(unix)]
+fn user_from_passwd() -> String {
+    unsafe {
+        let entry = libc::getpwuid(libc::geteuid());
+        if entry.is_null() || (*entry).pw_name.is_null() {
+            return String::new();
+        }
+        std::ffi::CStr::from_ptr((*entry).pw_name)
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+#[cfg(not(unix))]
+fn user_from_passwd() -> String {
+    String::new()
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } != 0 {
+        return String::new();
+    }
+    let end = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..end]).to_string()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+/// The parts of the current local time the prompt can show.
+struct Time {
+    hour: u32,
+    minute: u32,
+    second: u32,
+    /// 0 for Sunday
+    weekday: usize,
+    /// 0 for January
+    month: usize,
+    day: u32,
+}
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+impl Time {
+    fn format(&self, escape: char) -> String {
+        let hour12 = match self.hour % 12 {
+            0 => 12,
+            h => h,
+        };
+        match escape {
+            't' => format!("{:02}:{:02}:{:02}", self.hour, self.minute, self.second),
+            'T' => format!("{:02}:{:02}:{:02}", hour12, self.minute, self.second),
+            '@' => format!(
+                "{:02}:{:02} {}",
+                hour12,
+                self.minute,
+                if self.hour < 12 { "AM" } else { "PM" }
+            ),
+            'A' => format!("{:02}:{:02}", self.hour, self.minute),
+            _ => format!(
+                "{} {} {:02}",
+                WEEKDAYS[self.weekday % 7],
+                MONTHS[self.month % 12],
+                self.day
+            ),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn now() -> Time {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        let t = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&t, &mut tm);
+    }
+    Time {
+        hour: tm.tm_hour as u32,
+        minute: tm.tm_min as u32,
+        second: tm.tm_sec as u32,
+        weekday: tm.tm_wday as usize,
+        month: tm.tm_mon as usize,
+        day: tm.tm_mday as u32,
+    }
+}
+
+/// Without the C library to know the time zone, the time is in UTC.
+#[cfg(not(unix))]
+fn now() -> Time {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let days = secs / 86400;
+    // civil date from a day count, after Howard Hinnant's algorithm
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 2 } else { mp - 10 } as usize;
+    Time {
+        hour: (secs % 86400 / 3600) as u32,
+        minute: (secs % 3600 / 60) as u32,
+        second: (secs % 60) as u32,
+        weekday: ((days + 4) % 7) as usize,
+        month,
+        day,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreting::interpreter::Context;
+
+    use std::time::Duration;
+
+    use super::{abbreviate_home, format_duration, render, rows, visible_width, with_right, Time};
+
+    #[test]
+    fn test_escapes() {
+        let mut ctx = Context::new();
+        ctx.last_status = 3;
+        assert_eq!(render("[\\?]\\n\\\\ \\q", &ctx), "[3]\n\\ \\q");
+        assert_eq!(render("\\s-\\V", &ctx), "tinysh-0.0.3");
+        assert_eq!(render("\\101\\", &ctx), "A\\");
+        assert!(["$ ", "# "].contains(&render("\\$ ", &ctx).as_str()));
+    }
+
+    #[test]
+    fn test_colours_are_wrapped() {
+        let ctx = Context::new();
+        assert_eq!(
+            render("\\e[1;31mx\\e[0m", &ctx),
+            "\x01\x1b[1;31m\x02x\x01\x1b[0m\x02"
+        );
+        assert_eq!(
+            render("\\c{red}x\\c{reset}", &ctx),
+            "\x01\x1b[31m\x02x\x01\x1b[0m\x02"
+        );
+        assert_eq!(render("\\[\\e[1m\\]x", &ctx), "\x01\x1b[1m\x02x");
+    }
+
+    #[test]
+    fn test_home() {
+        assert_eq!(abbreviate_home("/home/a", "/home/a"), "~");
+        assert_eq!(abbreviate_home("/home/a/src", "/home/a"), "~/src");
+        assert_eq!(abbreviate_home("/home/ab", "/home/a"), "/home/ab");
+        assert_eq!(abbreviate_home("/tmp", "/"), "/tmp");
+    }
+
+    #[test]
+    fn test_time() {
+        let time = Time {
+            hour: 0,
+            minute: 5,
+            second: 9,
+            weekday: 2,
+            month: 4,
+            day: 26,
+        };
+        assert_eq!(time.format('t'), "00:05:09");
+        assert_eq!(time.format('T'), "12:05:09");
+        assert_eq!(time.format('@'), "12:05 AM");
+        assert_eq!(time.format('A'), "00:05");
+        assert_eq!(time.format('d'), "Tue May 26");
+    }
+
+    #[test]
+    fn test_right_prompt() {
+        assert_eq!(visible_width("\x01\x1b[31m\x02ab\x01\x1b[0m\x02"), 2);
+        assert_eq!(
+            with_right("top\n$ ", "\x01\x1b[1m\x0212:00", 20),
+            "top\n\x01\x1b7\x1b[16G\x1b[1m12:00\x1b8\x02$ "
+        );
+        assert_eq!(with_right("$ ", "12:00", 7), "$ ");
+        assert_eq!(with_right("$ ", "", 80), "$ ");
+    }
+
+    #[test]
+    fn test_rows() {
+        assert_eq!(rows("$ ", "", 10), 1);
+        assert_eq!(rows("$ ", "12345678", 10), 1);
+        assert_eq!(rows("$ ", "123456789", 10), 2);
+        assert_eq!(rows("a\n\x01\x1b[1m\x02$ ", "ls", 10), 2);
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(format_duration(Duration::from_millis(350)), "350ms");
+        assert_eq!(format_duration(Duration::from_millis(1540)), "1.5s");
+        assert_eq!(format_duration(Duration::from_secs(123)), "2m03s");
+        assert_eq!(format_duration(Duration::from_secs(3900)), "1h05m");
+    }
+}
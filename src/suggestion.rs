@@ -0,0 +1,197 @@
+//! Fish-style suggestions: the rest of the last history entry starting
+//! with the line, shown dimmed after it and taken with Right or End.
+//!
+//! `linefeed` has no hook to draw after the line, so the read loop draws
+//! between the steps of `read_line_step`, at the column where the line
+//! ends, and the keys that take or leave the suggestion are bound to the
+//! functions of `bind_keys`.
+
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use linefeed::{Command, Interface, Prompter, Terminal};
+
+use crate::{completion::ShellCompleter, prompt::visible_width};
+
+/// The rest of the most recent entry starting with `line`, or else of its
+/// only completion.
+pub fn suggest(line: &str, history: &[String], completer: &ShellCompleter) -> Option<String> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    history
+        .iter()
+        .rev()
+        .filter(|entry| !entry.contains('\n'))
+        .find_map(|entry| entry.strip_prefix(line).filter(|rest| !rest.is_empty()))
+        .map(|rest| rest.to_string())
+        .or_else(|| completer.suggestion(line))
+}
+
+/// What is on the screen after the line.
+#[derive(Debug, Default)]
+struct Shown {
+    /// The line the suggestion was made for
+    line: String,
+    suggestion: String,
+    /// Where it is drawn, from 1, and the number of columns it takes
+    column: usize,
+    width: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Suggestions {
+    shown: Mutex<Shown>,
+}
+
+impl Suggestions {
+    pub fn new() -> Suggestions {
+        Suggestions::default()
+    }
+
+    /// Draws `suggestion` after `line`, which ends before `column`, over
+    /// the previous one; columns from `limit` on are left to the right
+    /// prompt.
+    pub fn show(&self, line: &str, suggestion: Option<String>, column: usize, limit: usize) {
+        let mut shown = self.shown.lock().unwrap();
+        let mut suggestion = suggestion.unwrap_or_default();
+        let room = limit.saturating_sub(column);
+        if let Some((cut, _)) = suggestion.char_indices().nth(room) {
+            // only what fits is shown, but all of it is taken
+            let visible = &suggestion[..cut];
+            draw(column, visible, shown.column + shown.width);
+            shown.width = visible_width(visible);
+        } else {
+            draw(column, &suggestion, shown.column + shown.width);
+            shown.width = visible_width(&suggestion);
+        }
+        if room == 0 {
+            suggestion.clear();
+        }
+        shown.line = line.to_string();
+        shown.suggestion = suggestion;
+        shown.column = column;
+    }
+
+    /// Erases the suggestion, before the line is accepted.
+    pub fn clear(&self) {
+        let mut shown = self.shown.lock().unwrap();
+        draw(shown.column, "", shown.column + shown.width);
+        *shown = Shown::default();
+    }
+
+    /// The suggestion for `line`, if it is the one shown.
+    fn take(&self, line: &str) -> Option<String> {
+        let shown = self.shown.lock().unwrap();
+        (shown.line == line && !shown.suggestion.is_empty()).then(|| shown.suggestion.clone())
+    }
+}
+
+/// Writes `text` dimmed at `column` of the current row and blanks what is
+/// left of an older suggestion up to `end`, then puts the cursor back.
+fn draw(column: usize, text: &str, end: usize) {
+    if column == 0 || (text.is_empty() && end <= column) {
+        return;
+    }
+    let blank = " ".repeat(end.saturating_sub(column + visible_width(text)));
+    print!("\x1b7\x1b[{column}G\x1b[2m{text}\x1b[22m{blank}\x1b8");
+    let _ = std::io::stdout().flush();
+}
+
+/// Right and End take the suggestion when the cursor is at the end of the
+/// line, and move as usual otherwise; Enter erases it first.
+pub fn bind_keys<T: Terminal>(interface: &Interface<T>, suggestions: Arc<Suggestions>) {
+    let forward = suggestions.clone();
+    interface.define_function(
+        "forward-char-or-suggestion",
+        Arc::new(move |prompter: &mut Prompter<T>, count: i32, _| {
+            if let Some(rest) = at_end(prompter)
+                .then(|| forward.take(prompter.buffer()))
+                .flatten()
+            {
+                return prompter.insert_str(&rest);
+            }
+            let buffer = prompter.buffer();
+            let mut cursor = prompter.cursor();
+            for _ in 0..count.max(1) {
+                if let Some(c) = buffer[cursor..].chars().next() {
+                    cursor += c.len_utf8();
+                }
+            }
+            prompter.set_cursor(cursor)
+        }),
+    );
+    let end = suggestions.clone();
+    interface.define_function(
+        "end-of-line-or-suggestion",
+        Arc::new(move |prompter: &mut Prompter<T>, _, _| {
+            if let Some(rest) = at_end(prompter)
+                .then(|| end.take(prompter.buffer()))
+                .flatten()
+            {
+                return prompter.insert_str(&rest);
+            }
+            let length = prompter.buffer().len();
+            prompter.set_cursor(length)
+        }),
+    );
+    interface.define_function(
+        "accept-line-without-suggestion",
+        Arc::new(move |prompter: &mut Prompter<T>, _, _| {
+            suggestions.clear();
+            prompter.accept_input()
+        }),
+    );
+    let custom = |name: &'static str| Command::Custom(name.into());
+    for sequence in ["\x1b[C", "\x1bOC"] {
+        interface.bind_sequence(sequence, custom("forward-char-or-suggestion"));
+    }
+    for sequence in ["\x1b[F", "\x1bOF", "\x1b[4~", "\x05"] {
+        interface.bind_sequence(sequence, custom("end-of-line-or-suggestion"));
+    }
+    for sequence in ["\r", "\n"] {
+        interface.bind_sequence(sequence, custom("accept-line-without-suggestion"));
+    }
+}
+
+fn at_end<T: Terminal>(prompter: &Prompter<T>) -> bool {
+    prompter.cursor() == prompter.buffer().len()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::completion::ShellCompleter;
+
+    use super::{suggest, Suggestions};
+
+    #[test]
+    fn test_suggest() {
+        let history: Vec<String> = ["git status", "git stash", "for i in 1\ndo :\ndone"]
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        let completer = ShellCompleter::new();
+        assert_eq!(
+            suggest("git st", &history, &completer),
+            Some("ash".to_string())
+        );
+        assert_eq!(suggest("git stash", &history, &completer), None);
+        assert_eq!(suggest("for", &history, &completer), None);
+        assert_eq!(suggest("  ", &history, &completer), None);
+    }
+
+    #[test]
+    fn test_take() {
+        let suggestions = Suggestions::new();
+        suggestions.show("git st", Some("ash".to_string()), 0, 80);
+        assert_eq!(suggestions.take("git st"), Some("ash".to_string()));
+        assert_eq!(suggestions.take("git s"), None);
+        // no room before the right prompt
+        suggestions.show("git st", Some("ash".to_string()), 0, 0);
+        assert_eq!(suggestions.take("git st"), None);
+        suggestions.clear();
+        assert_eq!(suggestions.take("git st"), None);
+    }
+}
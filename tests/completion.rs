@@ -0,0 +1,35 @@
+//! The functions of `complete -F` run in a forked shell, which is only safe
+//! with no other test running beside them, so they have this file to
+//! themselves.
+
+#![cfg(unix)]
+
+use tinysh::{
+    completion::ShellCompleter,
+    interpreting::{interpreter::Context, script::run_source},
+};
+
+#[test]
+fn test_function_completions() {
+    let completer = ShellCompleter::new();
+    let mut ctx = Context::new();
+    let cwd = std::env::current_dir().unwrap();
+    run_source(
+        "_hosts() { cd /; export TINYSH_COMPLETED=1; cat; echo $2-one \"$3\"; }\n\
+         complete -F _hosts ssh",
+        &mut ctx,
+    );
+    completer.update(&ctx);
+    let names = |before: &str, word: &str| -> Vec<String> {
+        completer
+            .completions(before, word)
+            .into_iter()
+            .map(|c| c.completion)
+            .collect()
+    };
+    assert_eq!(names("ssh -p 22 ", "x"), vec!["x-one"]);
+    assert_eq!(names("ssh ", "s"), vec!["s-one", "ssh"]);
+    // nothing the function does reaches the shell
+    assert_eq!(std::env::current_dir().unwrap(), cwd);
+    assert!(std::env::var("TINYSH_COMPLETED").is_err());
+}
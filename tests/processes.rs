@@ -0,0 +1,67 @@
+//! Subshells and pipelines fork, which is not safe inside the threads of
+//! the test harness, so they are checked by running the shell itself, as
+//! is what a run without the prompt leaves in the home directory.
+
+#![cfg(unix)]
+
+use std::{path::PathBuf, process::Command};
+
+fn home() -> PathBuf {
+    std::env::temp_dir().join(format!("tinysh-home-{}", std::process::id()))
+}
+
+/// Runs `command` with `tinysh -c` in an empty home, and returns what it
+/// printed and its status.
+fn tinysh(command: &str) -> (String, i32) {
+    let home = home();
+    std::fs::create_dir_all(&home).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_tinysh"))
+        .args(["-c", command])
+        .env("HOME", &home)
+        .env_remove("XDG_CONFIG_HOME")
+        .output()
+        .unwrap();
+    (
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        output.status.code().unwrap_or(-1),
+    )
+}
+
+#[test]
+fn test_subshell_isolation() {
+    assert_eq!(tinysh("(a=1; exit 3)"), (String::new(), 3));
+    assert_eq!(
+        tinysh("(a=1; cd /; exit 3); echo $?-$a-; pwd"),
+        (
+            format!("3--\n{}\n", std::env::current_dir().unwrap().display()),
+            0
+        )
+    );
+}
+
+#[test]
+fn test_subshell_redirection() {
+    let path = std::env::temp_dir().join(format!("tinysh-subshell-{}", std::process::id()));
+    let command = format!("(echo a; echo b) > {}", path.display());
+    assert_eq!(tinysh(&command), (String::new(), 0));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb\n");
+    assert_eq!(
+        tinysh(&format!("cat < {} | grep b", path.display())),
+        ("b\n".to_string(), 0)
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_pipelines() {
+    assert_eq!(tinysh("echo a | cat | cat"), ("a\n".to_string(), 0));
+    assert_eq!(tinysh("true | false"), (String::new(), 1));
+    assert_eq!(tinysh("! true | false"), (String::new(), 0));
+}
+
+#[test]
+fn test_no_configuration() {
+    assert_eq!(tinysh("config get verbose"), ("true\n".to_string(), 0));
+    assert_eq!(tinysh("config set verbose false"), (String::new(), 0));
+    assert!(!home().join(".config").exists());
+}